@@ -0,0 +1,95 @@
+//! Compares `Pot::read`, which fills one reused line buffer, with feeding
+//! the same parser a fresh `String` per line from `BufRead::lines`, as the
+//! reader used to. Allocation counts for one read of each are printed
+//! before the timings.
+//!
+//! A catalog with a multi-megabyte line and one with a very long wrapped
+//! string check that reading stays linear in the length of a value.
+//!
+//! Run with `cargo bench`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use potty::{Parser, ParserOptions, Pot};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const ENTRIES: usize = 20_000;
+
+fn catalog(entries: usize) -> String {
+    let mut catalog = String::from("msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+    for i in 0..entries {
+        catalog.push_str(&format!(
+            "\n#: src/file{}.c:{}\nmsgid \"Message number {}\"\nmsgstr \"Nachricht Nummer {}\"\n",
+            i % 50, i, i, i,
+        ));
+    }
+    catalog
+}
+
+/// The reader before it reused its line buffer.
+fn read_per_line(catalog: &str) -> Pot {
+    let options = ParserOptions::default();
+    let mut parser = Parser::new(&options);
+    for line in BufReader::new(catalog.as_bytes()).lines() {
+        parser.feed_line(&line.unwrap());
+    }
+    parser.finish()
+}
+
+fn read_reused(catalog: &str) -> Pot {
+    Pot::read(&mut catalog.as_bytes())
+}
+
+fn allocations(f: impl Fn() -> Pot) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let pot = f();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    assert_eq!(pot.entries().count(), ENTRIES);
+    allocations
+}
+
+fn read(c: &mut Criterion) {
+    let catalog = catalog(ENTRIES);
+    println!("per-line String: {} allocations", allocations(|| read_per_line(&catalog)));
+    println!("reused buffer:   {} allocations", allocations(|| read_reused(&catalog)));
+
+    let mut group = c.benchmark_group("read");
+    group.bench_function("per-line String", |b| b.iter(|| read_per_line(black_box(&catalog))));
+    group.bench_function("reused buffer", |b| b.iter(|| read_reused(black_box(&catalog))));
+    group.finish();
+}
+
+fn long_values(c: &mut Criterion) {
+    let value = "Lorem ipsum \\\"dolor\\\" sit amet. ".repeat(100_000);
+    let long_line = format!("msgid \"long\"\nmsgstr \"{}\"\n", value);
+    let wrapped = format!("msgid \"long\"\nmsgstr \"\"\n{}", format!("\"{}\"\n", "Lorem ipsum dolor sit amet. ").repeat(100_000));
+
+    let mut group = c.benchmark_group("long values");
+    group.sample_size(10);
+    group.bench_function("single line", |b| b.iter(|| Pot::read(&mut black_box(long_line.as_bytes()))));
+    group.bench_function("wrapped", |b| b.iter(|| Pot::read(&mut black_box(wrapped.as_bytes()))));
+    group.finish();
+}
+
+criterion_group!(benches, read, long_values);
+criterion_main!(benches);
@@ -1,10 +1,20 @@
 use potty::{Pot};
+use std::env;
 use std::str;
 use std::fs::File;
 use std::io::{Cursor};
 use std::io::{BufReader, Result};
 
 fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() == 3 && args[1] == "ls" {
+        let file = File::open(&args[2])?;
+        let mut reader = BufReader::new(file);
+        let pot = Pot::read(&mut reader);
+        print!("{}", pot.summary());
+        return Ok(());
+    }
+
     let file = File::open("example.po")?;
     let mut reader = BufReader::new(file);
     let pot = Pot::read(&mut reader);
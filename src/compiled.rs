@@ -0,0 +1,87 @@
+use std::ops::Range;
+
+use crate::Pot;
+
+struct Entry {
+    key: Range<usize>,
+    forms: Range<usize>,
+}
+
+/// A read-only catalog built by `Pot::compile` for fast lookups.
+///
+/// All keys and translations live in a single string arena, entries are
+/// sorted by key for binary search, and the plural rule is compiled once.
+/// Only live, translated, non-fuzzy entries are kept.
+pub struct CompiledCatalog {
+    arena: String,
+    forms: Vec<Range<usize>>,
+    entries: Vec<Entry>,
+    plural: Box<dyn Fn(u64) -> usize + Send + Sync>,
+}
+
+impl CompiledCatalog {
+    pub(crate) fn new(pot: Pot) -> CompiledCatalog {
+        let plural = pot.plural_selector().unwrap_or_else(|_| Box::new(|n| (n != 1) as usize));
+        let mut messages: Vec<_> = pot.entries()
+            .filter(|m| m.is_translated() && !m.is_fuzzy())
+            .map(|m| (m.key(), &m.strings))
+            .collect();
+        messages.sort_by(|a, b| a.0.cmp(&b.0));
+        messages.dedup_by(|a, b| a.0 == b.0);
+
+        let mut arena = String::new();
+        let mut forms = Vec::new();
+        let mut entries = Vec::with_capacity(messages.len());
+        let push = |arena: &mut String, s: &str| {
+            let start = arena.len();
+            arena.push_str(s);
+            start..arena.len()
+        };
+        for (key, strings) in messages {
+            let key = push(&mut arena, &key);
+            let first_form = forms.len();
+            for string in strings {
+                forms.push(push(&mut arena, string));
+            }
+            entries.push(Entry { key, forms: first_form..forms.len() });
+        }
+        arena.shrink_to_fit();
+
+        CompiledCatalog { arena, forms, entries, plural }
+    }
+
+    fn lookup(&self, key: &str) -> Option<&Entry> {
+        self.entries
+            .binary_search_by(|e| self.arena[e.key.clone()].cmp(key))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    fn form(&self, entry: &Entry, index: usize) -> Option<&str> {
+        self.forms[entry.forms.clone()].get(index).map(|range| &self.arena[range.clone()])
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn gettext<'a>(&'a self, msgid: &'a str) -> &'a str {
+        self.lookup(msgid).and_then(|e| self.form(e, 0)).unwrap_or(msgid)
+    }
+
+    pub fn pgettext<'a>(&'a self, context: &str, msgid: &'a str) -> &'a str {
+        self.lookup(&format!("{}\u{4}{}", context, msgid)).and_then(|e| self.form(e, 0)).unwrap_or(msgid)
+    }
+
+    pub fn ngettext<'a>(&'a self, msgid: &'a str, msgid_plural: &'a str, n: u64) -> &'a str {
+        match self.lookup(msgid).and_then(|e| self.form(e, (self.plural)(n))) {
+            Some(translation) => translation,
+            None if n == 1 => msgid,
+            None => msgid_plural,
+        }
+    }
+}
@@ -1,26 +1,200 @@
 use std::fmt;
+use std::borrow::Cow;
+use std::fmt::Write as _;
 use std::str::FromStr;
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
 use std::io::{Read, Write, BufRead, BufReader};
-use unescape::unescape;
 use regex::Regex;
 
+mod compiled;
+mod memory;
+mod plural;
+
+pub use compiled::CompiledCatalog;
+pub use memory::{Suggestion, TranslationMemory};
+pub use plural::PluralParseError;
+
+#[derive(Default, PartialEq)]
 pub struct Pot {
     pub messages: Vec<PotMessage>,
+    pub obsolete_lines: Vec<String>,
+    /// Comment lines after the last entry, such as a note closing the file,
+    /// kept as raw text and written back after everything else. Blank lines
+    /// that separated them from the last entry are kept as empty lines.
+    pub trailing_comments: Vec<String>,
+    plural: plural::Cache,
+}
+
+/// Options controlling how `Pot::read_with_options` interprets its input.
+pub struct ParserOptions {
+    /// Keep `#~` lines as raw text in `Pot::obsolete_lines` instead of parsing
+    /// them into obsolete messages. The lines are written back verbatim after
+    /// the live entries, but they can't be inspected or edited as messages.
+    pub collapse_obsolete: bool,
+    /// Extra keywords to accept, mapped to the standard keyword they stand in
+    /// for, e.g. `"msgtxt" => "msgstr"`.
+    pub keyword_aliases: HashMap<String, String>,
+    /// Prefix that starts a comment line. Lines using it are read as if they
+    /// started with `#`, so comment kinds like `;:` or `//,` still apply.
+    pub comment_prefix: String,
+    /// Keep each message's original text in `PotMessage::original`, so
+    /// messages left unchanged are written back byte for byte.
+    pub keep_original: bool,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            collapse_obsolete: false,
+            keyword_aliases: HashMap::new(),
+            comment_prefix: "#".to_string(),
+            keep_original: false,
+        }
+    }
+}
+
+/// Incremental reader that is fed a catalog one line at a time, e.g. to
+/// parse a large file in chunks. `Pot::read` uses it internally.
+pub struct Parser<'a> {
+    options: &'a ParserOptions,
+    pot: Pot,
+    message: PotMessage,
+    command: PotCommand,
+    line_number: usize,
+    offset: usize,
+    syncing: bool,
+    plural_pending: bool,
+    keyword: Regex,
+    continuation: Regex,
+    prefixed: String,
+    stripped: String,
+    /// Lines since the current message's last one that belong to no
+    /// message, such as blank lines. Only kept with `keep_original`.
+    skipped: String,
+    /// The skipped lines that came before the pending comments.
+    leading: String,
+    /// Comments read after a complete message, or before any keyword of a
+    /// new one. They are held until the next keyword shows whether they
+    /// continue that message or start the next.
+    pending: Vec<PotComment>,
+    pending_span: Option<Range<usize>>,
+    /// The raw lines of the pending comments and blank lines among them.
+    pending_text: String,
+    /// Blank lines between the current message and the pending comments.
+    pending_gap: usize,
+    /// Blank lines since the last line that wasn't blank.
+    blank_lines: usize,
+}
+
+/// Options controlling how `Pot::write_with_options` lays out its output.
+pub struct WriteOptions {
+    /// Merge every `#:` line of a message into a single reference line.
+    pub fold_references: bool,
+    /// Reproduce the layout recorded when the catalog was read, such as
+    /// comments placed between keyword lines or obsolete entries mixed in
+    /// with live ones. By default comments are written before the keywords
+    /// and obsolete entries are grouped at the end, as gettext does.
+    pub faithful: bool,
+    /// The kinds of comment to write; others are left out of the output
+    /// without being removed from the catalog.
+    pub comment_kinds: Vec<PotCommentKind>,
+    /// The terminator written after every line.
+    pub line_ending: LineEnding,
+}
+
+/// Line terminator used by `Pot::write_with_options`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    /// `\r\n`, for Windows tools that expect it.
+    Crlf,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            fold_references: false,
+            faithful: false,
+            comment_kinds: vec![
+                PotCommentKind::Translator,
+                PotCommentKind::Extracted,
+                PotCommentKind::Reference,
+                PotCommentKind::Flag,
+                PotCommentKind::Previous,
+            ],
+            line_ending: LineEnding::Lf,
+        }
+    }
 }
 
+#[derive(Default, Clone)]
 pub struct PotMessage {
     pub comments: Vec<PotComment>,
     pub context: Option<String>,
     pub id: Option<String>,
     pub id_plural: Option<String>,
     pub strings: Vec<String>,
+    pub obsolete: bool,
+    /// Arbitrary data attached by the application, such as editor state. It
+    /// is never written out and is ignored when comparing messages.
+    pub extra: Option<Arc<dyn Any + Send + Sync>>,
+    /// The byte range the message occupied in the input it was read from.
+    /// Like `extra`, it is ignored when comparing messages.
+    pub span: Option<Range<usize>>,
+    /// The message's lines exactly as read, kept when reading with
+    /// `ParserOptions::keep_original`. Writing reuses them verbatim as long as
+    /// the message hasn't been changed since. Ignored when comparing.
+    pub original: Option<OriginalText>,
+}
+
+/// Source text of a message as read, see `PotMessage::original`.
+#[derive(Clone)]
+pub struct OriginalText {
+    text: String,
+    fingerprint: u64,
+}
+
+impl PartialEq for PotMessage {
+    fn eq(&self, other: &Self) -> bool {
+        self.comments == other.comments
+            && self.context == other.context
+            && self.id == other.id
+            && self.id_plural == other.id_plural
+            && self.strings == other.strings
+            && self.obsolete == other.obsolete
+    }
 }
 
+#[derive(Clone)]
 pub struct PotComment {
     pub kind: PotCommentKind,
     pub content: String,
+    /// How many keyword lines of its message preceded the comment in the
+    /// source, used to put it back in place by `WriteOptions::faithful`.
+    pub fields_before: usize,
+}
+
+// Comments compare by what gets written, so neither their position among
+// the keyword lines nor the order of `fuzzy` in a flag list matters.
+impl PartialEq for PotComment {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.written_content() == other.written_content()
+    }
 }
 
+impl Hash for PotComment {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.kind.hash(state);
+        self.written_content().hash(state);
+    }
+}
+
+#[derive(Clone, PartialEq, Hash)]
 pub enum PotCommentKind {
     Reference,
     Extracted,
@@ -29,79 +203,804 @@ pub enum PotCommentKind {
     Translator,
 }
 
+/// How `Pot::entries_referencing_with` compares reference paths.
+pub enum PathMatch {
+    Exact,
+    /// Matches when the reference path ends with the given path on a `/`
+    /// boundary, so `ui/menu.rs` matches `src/ui/menu.rs`.
+    Suffix,
+}
+
+/// Ordering used by `Pot::sort`.
+pub enum SortKey {
+    /// By msgid, then context.
+    Msgid,
+    /// By the first `#:` reference (file, then line), as xgettext orders its
+    /// output. Entries without references go last.
+    Reference,
+}
+
+/// Target format for the values yielded by `Pot::escaped_pairs`.
+pub enum EscapeStyle {
+    /// The contents of a JSON string literal, without the surrounding quotes.
+    Json,
+    /// XML text or attribute content, with `&<>"'` as entities.
+    Xml,
+    /// The contents of a single-line PO string, with newlines as `\n`.
+    Po,
+}
+
+/// Options controlling `Pot::sort_with`. The header always stays first.
+#[derive(Default)]
+pub struct SortOptions {
+    /// Keep obsolete entries after all live ones, sorted among themselves.
+    pub obsolete_last: bool,
+}
+
+/// Quote style `Pot::normalize_quotes_with` converts translations to.
+pub enum QuoteStyle {
+    /// `“ ”` and `‘ ’` become `"` and `'`.
+    Straight,
+    /// `"` becomes alternating `“` and `”`, and `'` becomes `’`.
+    Curly,
+}
+
+/// Translation progress of a catalog's live entries, as returned by
+/// `Pot::stats`. Obsolete entries are only counted in `obsolete`.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub struct PotStats {
+    pub total: usize,
+    pub translated: usize,
+    pub fuzzy: usize,
+    pub untranslated: usize,
+    pub obsolete: usize,
+}
+
+/// Options controlling which checks `Pot::validate_with` runs.
+pub struct ValidateOptions {
+    /// Control characters translations may contain without being reported.
+    pub allowed_control_characters: Vec<char>,
+    /// Report msgids used under more than one context. Off by default since
+    /// reusing a msgid across contexts is valid gettext.
+    pub unique_msgids: bool,
+    /// Accelerator marker, such as `&` or `_`, whose count must match between
+    /// a msgid and its translations. A doubled marker is a literal character.
+    /// Off when `None`.
+    pub accelerator: Option<char>,
+    /// Check that translations keep the same `<tag>`s as their source.
+    pub markup: bool,
+    /// Tag names, such as `br`, the markup check ignores.
+    pub ignored_tags: Vec<String>,
+}
+
+impl Default for ValidateOptions {
+    fn default() -> Self {
+        ValidateOptions {
+            allowed_control_characters: vec!['\n', '\t'],
+            unique_msgids: false,
+            accelerator: None,
+            markup: false,
+            ignored_tags: Vec::new(),
+        }
+    }
+}
+
+/// A problem found by `Pot::validate`. `index` is the position of the
+/// offending message in `Pot::messages`.
+#[derive(Debug, PartialEq)]
+pub enum ValidationIssue {
+    /// A translation contains a control character outside the allowed set.
+    ControlCharacter { index: usize, codepoint: u32 },
+    /// A translation doesn't start or end with `\n` where its source does, or
+    /// vice versa.
+    NewlineMismatch { index: usize },
+    /// The same msgid appears under different contexts. Only reported when
+    /// `ValidateOptions::unique_msgids` is set.
+    DuplicateMsgidAcrossContexts { id: String, indices: Vec<usize> },
+    /// A translation has a different number of accelerator markers than its
+    /// source. Only reported when `ValidateOptions::accelerator` is set.
+    AcceleratorMismatch { index: usize },
+    /// A translation drops, adds or changes a markup tag of its source. Only
+    /// reported when `ValidateOptions::markup` is set.
+    MarkupMismatch { index: usize },
+    /// A plural entry whose `msgid` or `msgid_plural` is empty.
+    EmptyPluralSource { index: usize },
+}
+
+/// A problem with the header's MIME fields found by
+/// `Pot::validate_header_mime`, with the value that would fix it.
+#[derive(Debug, PartialEq)]
+pub enum HeaderIssue {
+    /// The field isn't present in the header.
+    Missing { field: String, suggestion: String },
+    /// The field is present but its value isn't acceptable.
+    Malformed { field: String, value: String, suggestion: String },
+}
+
+/// Options controlling how `Pot::merge3_with` matches entries.
+#[derive(Default)]
+pub struct MergeOptions {
+    /// Match msgids that differ only in runs of whitespace or leading and
+    /// trailing whitespace. Stored msgids are never changed.
+    pub normalize_whitespace: bool,
+    /// Keys, as returned by `PotMessage::key`, of human-approved entries.
+    /// Ours is kept for them as is, never overwritten or marked fuzzy.
+    pub locked: BTreeSet<String>,
+}
+
+/// An entry `Pot::merge3` couldn't merge because both sides changed its
+/// translation differently. Each version is `None` where the entry doesn't
+/// exist on that side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub context: Option<String>,
+    pub id: String,
+    pub base: Option<Vec<String>>,
+    pub ours: Option<Vec<String>>,
+    pub theirs: Option<Vec<String>>,
+}
+
+/// Something `Pot::read_with_warnings` repaired or skipped. `line` is
+/// 1-based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseWarning {
+    pub line: usize,
+    pub kind: ParseWarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseWarningKind {
+    /// A second `msgstr` for a message whose first one was empty. The two are
+    /// collapsed into one string.
+    DuplicateMsgstr,
+    /// A keyword that can't start a message, such as `msgstr` before any
+    /// `msgid`. The line is skipped.
+    MisplacedKeyword(String),
+    /// A keyword that isn't part of the PO format, such as a misspelt
+    /// `msgsrt`. The line is skipped.
+    UnknownKeyword(String),
+    /// A `msgid_plural` that follows the message's `msgstr[n]` lines instead
+    /// of preceding them. It is attached to the message anyway.
+    MsgidPluralAfterMsgstr,
+    /// Bytes that aren't valid UTF-8, starting at `offset` bytes into the
+    /// input. They are read as U+FFFD replacement characters.
+    InvalidUtf8 { offset: usize, bytes: Vec<u8> },
+    /// A `msgstr[n]` whose index is `MAX_PLURAL_FORMS` or more, saturated to
+    /// `usize::MAX` when it doesn't even fit. The line is skipped.
+    PluralIndexOutOfRange(usize),
+}
+
+/// The highest number of plural forms the parser accepts, well above what
+/// any language needs. `msgstr[n]` lines with a larger index are skipped
+/// rather than allocating a form for every index up to them.
+pub const MAX_PLURAL_FORMS: usize = 32;
+
+/// What `Pot::gettext_with` returns when there's no usable translation.
+pub enum Fallback {
+    /// The msgid itself, as `gettext` does.
+    Msgid,
+    Empty,
+    /// The `[[missing]]` marker, to make gaps visible in QA builds.
+    Marked,
+}
+
+/// Error returned by `Pot::try_format` when the placeholders and arguments
+/// don't line up. Indices are zero-based.
+#[derive(Debug, PartialEq)]
+pub enum FormatError {
+    MissingArgument(usize),
+    UnusedArgument(usize),
+}
+
+struct FormattedMessage<'a> {
+    message: &'a PotMessage,
+    options: &'a WriteOptions,
+    /// Number of empty forms to write for an untranslated plural entry.
+    nplurals: usize,
+}
+
+#[derive(Default)]
 struct PotCommand {
     key: String,
     value: String,
     index: Option<usize>
 }
 
-impl Default for PotMessage {
-    fn default() -> Self {
-        PotMessage {
-            comments: Vec::new(),
-            context: None,
-            id: None,
-            id_plural: None,
-            strings: Vec::new(),
+/// Returns gettext's recommended `Plural-Forms` value for a language code
+/// such as `de` or `pt_BR`.
+fn plural_forms_for(language: &str) -> Option<&'static str> {
+    let lookup = |language: &str| Some(match language {
+        "ja" | "ko" | "th" | "vi" | "zh" | "id" | "ms" => "nplurals=1; plural=0;",
+        "pt_BR" | "fr" => "nplurals=2; plural=(n > 1);",
+        "en" | "de" | "nl" | "sv" | "da" | "no" | "nb" | "nn" | "fo" | "es" | "pt" | "it" | "bg" | "el" | "fi" | "et" | "he" | "eo" | "hu" | "tr" =>
+            "nplurals=2; plural=(n != 1);",
+        "lv" => "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n != 0 ? 1 : 2);",
+        "ga" => "nplurals=3; plural=n==1 ? 0 : n==2 ? 1 : 2;",
+        "ro" => "nplurals=3; plural=n==1 ? 0 : (n==0 || (n%100 > 0 && n%100 < 20)) ? 1 : 2;",
+        "lt" => "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && (n%100<10 || n%100>=20) ? 1 : 2);",
+        "ru" | "uk" | "be" | "sr" | "hr" | "bs" =>
+            "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);",
+        "cs" | "sk" => "nplurals=3; plural=(n==1) ? 0 : (n>=2 && n<=4) ? 1 : 2;",
+        "pl" => "nplurals=3; plural=(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);",
+        "sl" => "nplurals=4; plural=(n%100==1 ? 0 : n%100==2 ? 1 : n%100==3 || n%100==4 ? 2 : 3);",
+        "ar" => "nplurals=6; plural=n==0 ? 0 : n==1 ? 1 : n==2 ? 2 : n%100>=3 && n%100<=10 ? 3 : n%100>=11 ? 4 : 5;",
+        _ => return None,
+    });
+    lookup(language).or_else(|| lookup(language.split(['_', '-', '@']).next()?))
+}
+
+/// Escapes `s` the way gettext does, except for newlines which are left for
+/// `format_string` to split on. Quotes, backslashes, tabs and carriage
+/// returns get their short escapes, other ASCII control characters are
+/// written in octal and everything else is kept literal.
+fn escape_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push('\n'),
+            '\u{7}' => escaped.push_str("\\a"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{b}' => escaped.push_str("\\v"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if c.is_ascii_control() => write!(escaped, "\\{:03o}", c as u32).unwrap(),
+            c => escaped.push(c),
         }
     }
+    escaped
 }
 
-fn format_string(s: &str) -> String {
-    let s = s.replace("\"", "\\\"").replace("\r", "\\r").replace("\t", "\\t");
-    let lines = s.split("\n").enumerate();
-    let lines_count = lines.clone().count();
-    lines.map(|(i, s)| {
-        if i == lines_count - 1 {
-            return format!("\"{}\"", s)
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl EscapeStyle {
+    fn escape(&self, s: &str) -> String {
+        match self {
+            EscapeStyle::Json => escape_json(s),
+            EscapeStyle::Xml => escape_xml(s),
+            EscapeStyle::Po => escape_string(s).replace('\n', "\\n"),
+        }
+    }
+}
+
+impl QuoteStyle {
+    fn apply(&self, s: &str) -> String {
+        match self {
+            QuoteStyle::Straight => s.replace(['“', '”'], "\"").replace(['‘', '’'], "'"),
+            QuoteStyle::Curly => {
+                let mut open = true;
+                s.chars().map(|c| match c {
+                    '"' => {
+                        open = !open;
+                        if open { '”' } else { '“' }
+                    },
+                    '\'' => '’',
+                    c => c,
+                }).collect()
+            },
+        }
+    }
+}
+
+fn interpolate(s: &str, args: &[&str], strict: bool) -> Result<String, FormatError> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"%(?:%|([1-9][0-9]*)\$s|s)").unwrap());
+    let mut used = vec![false; args.len()];
+    let mut next = 0;
+    let mut result = String::with_capacity(s.len());
+    let mut last = 0;
+    for caps in re.captures_iter(s) {
+        let m = caps.get(0).unwrap();
+        result.push_str(&s[last..m.start()]);
+        last = m.end();
+        if m.as_str() == "%%" {
+            result.push('%');
+            continue;
+        }
+        let index = match caps.get(1) {
+            Some(position) => position.as_str().parse::<usize>().map(|p| p - 1).unwrap_or(usize::MAX),
+            None => { next += 1; next - 1 },
+        };
+        match args.get(index) {
+            Some(arg) => {
+                result.push_str(arg);
+                used[index] = true;
+            },
+            None if strict => return Err(FormatError::MissingArgument(index)),
+            None => result.push_str(m.as_str()),
+        }
+    }
+    result.push_str(&s[last..]);
+    if strict {
+        if let Some(index) = used.iter().position(|used| !used) {
+            return Err(FormatError::UnusedArgument(index));
+        }
+    }
+    Ok(result)
+}
+
+/// Counts the accelerator markers in `s`, skipping doubled ones which stand
+/// for the literal character.
+fn count_accelerators(s: &str, marker: char) -> usize {
+    let mut count = 0;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != marker {
+            continue;
+        }
+        match chars.peek() {
+            Some(&next) if next == marker => { chars.next(); },
+            Some(next) if next.is_alphanumeric() => count += 1,
+            _ => (),
+        }
+    }
+    count
+}
+
+/// Returns the sorted markup tags in `s` as `b`, `/b` or `br/`, skipping
+/// the names in `ignored`.
+fn markup_tags(re: &Regex, s: &str, ignored: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = re.captures_iter(s)
+        .filter(|caps| !ignored.iter().any(|name| name.eq_ignore_ascii_case(&caps[2])))
+        .map(|caps| format!("{}{}{}", &caps[1], caps[2].to_ascii_lowercase(), &caps[3]))
+        .collect();
+    tags.sort();
+    tags
+}
+
+/// Collapses runs of whitespace into a single space and trims the ends.
+fn normalize_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Shortens `s` to `max` characters for terminal output, escaping control
+/// characters so they can't mess with the display.
+fn abbreviate(s: &str, max: usize) -> String {
+    let escaped: String = s.chars().map(|c| {
+        if c.is_control() { c.escape_default().collect() } else { c.to_string() }
+    }).collect();
+    if escaped.chars().count() <= max {
+        return escaped;
+    }
+    let mut abbreviated: String = escaped.chars().take(max.saturating_sub(1)).collect();
+    abbreviated.push('…');
+    abbreviated
+}
+
+/// Decodes the escape sequences of a quoted PO string, as documented on
+/// `Pot::read`.
+fn unescape_string(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let digits = |start: usize, max: usize, radix: u32| {
+        chars.iter().skip(start).take(max).take_while(|d| d.is_digit(radix)).count()
+    };
+    let value = |range: Range<usize>, radix: u32| {
+        u32::from_str_radix(&chars[range].iter().collect::<String>(), radix).unwrap()
+    };
+    let mut unescaped = String::with_capacity(s.len());
+    // Octal and hex escapes give bytes, which are decoded together so that a
+    // UTF-8 sequence such as `\303\251` comes out as a single character.
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '\\' || i + 1 == chars.len() {
+            flush_bytes(&mut bytes, &mut unescaped);
+            unescaped.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let escape = chars[i + 1];
+        let byte = match escape {
+            '0'..='7' => Some(1 + digits(i + 2, 2, 8)).map(|n| (value(i + 1..i + 1 + n, 8), 1 + n)),
+            'x' => Some(digits(i + 2, 2, 16)).filter(|&n| n > 0).map(|n| (value(i + 2..i + 2 + n, 16), 2 + n)),
+            _ => None,
+        };
+        if let Some((byte, len)) = byte.filter(|&(byte, _)| byte <= 0xff) {
+            bytes.push(byte as u8);
+            i += len;
+            continue;
+        }
+        flush_bytes(&mut bytes, &mut unescaped);
+        let (decoded, len) = match escape {
+            'a' => (Some('\u{7}'), 2),
+            'b' => (Some('\u{8}'), 2),
+            'f' => (Some('\u{c}'), 2),
+            'n' => (Some('\n'), 2),
+            'r' => (Some('\r'), 2),
+            't' => (Some('\t'), 2),
+            'v' => (Some('\u{b}'), 2),
+            '\\' | '"' | '\'' | '?' => (Some(escape), 2),
+            'u' | 'U' => {
+                let width = if escape == 'u' { 4 } else { 8 };
+                if digits(i + 2, width, 16) < width {
+                    (None, 2)
+                } else {
+                    let code = value(i + 2..i + 2 + width, 16);
+                    let low = i + 2 + width;
+                    let pair = escape == 'u'
+                        && (0xd800..0xdc00).contains(&code)
+                        && chars.get(low) == Some(&'\\')
+                        && chars.get(low + 1) == Some(&'u')
+                        && digits(low + 2, 4, 16) == 4
+                        && (0xdc00..0xe000).contains(&value(low + 2..low + 6, 16));
+                    if pair {
+                        let code = 0x10000 + ((code - 0xd800) << 10) + (value(low + 2..low + 6, 16) - 0xdc00);
+                        (char::from_u32(code), 2 + width + 6)
+                    } else {
+                        (char::from_u32(code), 2 + width)
+                    }
+                }
+            },
+            _ => (None, 2),
+        };
+        match decoded {
+            Some(c) => unescaped.push(c),
+            None => unescaped.extend(&chars[i..i + len]),
         }
-        let ss = format!("\"{}\\n\"\n", s);
-        if i == 0 && !s.is_empty() {
-            return format!("\"\"\n{}", ss)
+        i += len;
+    }
+    flush_bytes(&mut bytes, &mut unescaped);
+    unescaped
+}
+
+/// Appends the bytes collected from octal and hex escapes as UTF-8. Bytes
+/// that are not valid UTF-8 are taken as Latin-1.
+fn flush_bytes(bytes: &mut Vec<u8>, unescaped: &mut String) {
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                unescaped.push_str(valid);
+                break;
+            },
+            Err(error) => {
+                let (valid, invalid) = rest.split_at(error.valid_up_to());
+                unescaped.push_str(std::str::from_utf8(valid).unwrap());
+                unescaped.push(invalid[0] as char);
+                rest = &invalid[1..];
+            },
         }
-        ss
-    }).collect()
+    }
+    bytes.clear();
+}
+
+/// Quotes `s` for writing. Like gettext, a string with embedded newlines
+/// starts with an empty `""` line and then puts each `...\n` segment on its
+/// own line, so header fields come out one per line.
+fn format_string(s: &str) -> String {
+    let s = escape_string(s);
+    let segments: Vec<&str> = s.split_inclusive('\n').collect();
+    if segments.len() <= 1 {
+        return format!("\"{}\"", s.replace('\n', "\\n"));
+    }
+    let mut formatted = String::from("\"\"");
+    for segment in segments {
+        write!(formatted, "\n\"{}\"", segment.replace('\n', "\\n")).unwrap();
+    }
+    formatted
+}
+
+/// Returns the `printf`-style conversion specifiers in `s`, in order, e.g.
+/// `["%1$s", "%5.2f"]`. Escaped `%%` sequences are skipped.
+pub fn count_c_format_specifiers(s: &str) -> Vec<String> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r"%(?:%|(?:[0-9]+\$)?[-+ #0'I]*(?:[0-9]+|\*)?(?:\.(?:[0-9]+|\*)?)?(?:hh|h|ll|l|L|q|j|z|Z|t)?[diouxXeEfFgGaAcspnCSm])").unwrap()
+    });
+    re.find_iter(s)
+        .map(|m| m.as_str())
+        .filter(|m| *m != "%%")
+        .map(|m| m.to_string())
+        .collect()
 }
 
 impl fmt::Display for PotMessage {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for comment in &self.comments {
-            writeln!(f, "{}", comment)?;
+        self.fmt_with_options(f, &WriteOptions::default(), 2)
+	}
+}
+
+impl fmt::Display for FormattedMessage<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.message.fmt_with_options(f, self.options, self.nplurals)
+	}
+}
+
+impl PotMessage {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_header(&self) -> bool {
+        self.id.as_deref() == Some("") && self.context.is_none()
+    }
+
+    pub fn flags(&self) -> Vec<&str> {
+        self.comments.iter()
+            .filter(|c| c.kind == PotCommentKind::Flag)
+            .flat_map(|c| c.content.split(','))
+            .map(|f| f.trim())
+            .filter(|f| !f.is_empty())
+            .collect()
+    }
+
+    pub fn is_fuzzy(&self) -> bool {
+        self.flags().iter().any(|f| f.eq_ignore_ascii_case("fuzzy"))
+    }
+
+    /// Adds the `fuzzy` flag, putting it first on an existing `#,` line if
+    /// there is one.
+    pub fn mark_fuzzy(&mut self) {
+        if self.is_fuzzy() {
+            return;
+        }
+        match self.comments.iter_mut().find(|c| c.kind == PotCommentKind::Flag) {
+            Some(comment) if comment.content.trim().is_empty() => comment.content = "fuzzy".to_string(),
+            Some(comment) => comment.content.insert_str(0, "fuzzy, "),
+            None => self.comments.push(PotComment {
+                kind: PotCommentKind::Flag,
+                content: "fuzzy".to_string(),
+                fields_before: 0,
+            }),
+        }
+    }
+
+    pub fn is_translated(&self) -> bool {
+        !self.strings.is_empty() && self.strings.iter().all(|s| !s.is_empty())
+    }
+
+    /// Like `is_translated`, but a plural entry also needs at least
+    /// `nplurals` forms when the catalog's header gives that count.
+    fn is_fully_translated(&self, nplurals: Option<usize>) -> bool {
+        self.is_translated() && (self.id_plural.is_none() || nplurals.is_none_or(|n| self.strings.len() >= n))
+    }
+
+    /// Returns every source reference of the message as `(path, line)` pairs,
+    /// collected across all of its `#:` comment lines.
+    pub fn references(&self) -> Vec<(&str, Option<u32>)> {
+        self.comments.iter()
+            .filter(|c| c.kind == PotCommentKind::Reference)
+            .flat_map(|c| c.content.split_whitespace())
+            .map(|r| match r.rsplit_once(':') {
+                Some((path, line)) => match line.parse::<u32>() {
+                    Ok(line) => (path, Some(line)),
+                    Err(_) => (r, None),
+                },
+                None => (r, None),
+            })
+            .collect()
+    }
+
+    pub fn set_extra<T: Any + Send + Sync>(&mut self, value: T) {
+        self.extra = Some(Arc::new(value));
+    }
+
+    pub fn extra<T: Any>(&self) -> Option<&T> {
+        self.extra.as_ref()?.downcast_ref()
+    }
+
+    /// Counts the whitespace-separated words of the msgid.
+    pub fn word_count(&self) -> usize {
+        self.id.as_deref().unwrap_or_default().split_whitespace().count()
+    }
+
+    /// Returns the key gettext looks this message up by: the msgid, prefixed
+    /// with the context and an EOT (`\u{4}`) separator when there is one.
+    pub fn key(&self) -> String {
+        let id = self.id.as_deref().unwrap_or_default();
+        match self.context {
+            Some(ref ctx) => format!("{}\u{4}{}", ctx, id),
+            None => id.to_string(),
+        }
+    }
+
+    /// Puts the plural forms in `msgstr[n]` order. This is a no-op, as
+    /// `strings` is always indexed by form: the parser stores `msgstr[1]` at
+    /// index 1 even when it comes before `msgstr[0]`, and `Display` writes
+    /// the forms in index order.
+    pub fn sort_plural_forms(&mut self) {}
+
+    /// Returns the first `#:` reference, where editors jump to.
+    pub fn primary_reference(&self) -> Option<(&str, Option<u32>)> {
+        self.references().into_iter().next()
+    }
+
+    /// Returns the references deduplicated and sorted by path, then line.
+    pub fn normalized_references(&self) -> Vec<(String, Option<u32>)> {
+        self.references().into_iter()
+            .map(|(path, line)| (path.to_string(), line))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn translator_note(&self) -> String {
+        self.comments.iter()
+            .filter(|c| c.kind == PotCommentKind::Translator)
+            .map(|c| c.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Replaces the translator comments with one `#` line per line of `note`.
+    pub fn set_translator_note(&mut self, note: &str) {
+        self.comments.retain(|c| c.kind != PotCommentKind::Translator);
+        if note.is_empty() {
+            return;
         }
+        let comments = note.lines().map(|line| PotComment{
+            kind: PotCommentKind::Translator,
+            content: line.to_string(),
+            fields_before: 0,
+        });
+        self.comments.splice(0..0, comments);
+    }
+
+    fn fmt_with_options(&self, f: &mut fmt::Formatter, options: &WriteOptions, nplurals: usize) -> fmt::Result {
+        let prefix = if self.obsolete { "#~ " } else { "" };
+        let format = |s: &str| format_string(s).replace('\n', &format!("\n{}", prefix));
+        let mut fields = Vec::new();
         if let Some(ref ctx) = self.context {
-            writeln!(f, "msgctxt \"{}\"", ctx)?;
+            fields.push(format!("{}msgctxt {}", prefix, format(ctx)));
         }
         if let Some(ref id) = self.id {
-            writeln!(f, "msgid \"{}\"", id)?;
+            fields.push(format!("{}msgid {}", prefix, format(id)));
         }
         if let Some(ref id_plural) = self.id_plural {
-            writeln!(f, "msgid_plural \"{}\"", id_plural)?;
+            fields.push(format!("{}msgid_plural {}", prefix, format(id_plural)));
+        }
+        if self.id_plural.is_some() && self.strings.is_empty() {
+            for i in 0..nplurals {
+                fields.push(format!("{}msgstr[{}] \"\"", prefix, i));
+            }
         }
         for (i, string) in self.strings.iter().enumerate() {
-            let string = format_string(string);
+            let string = format(string);
             if self.id_plural.is_some() {
-                writeln!(f, "msgstr[{}] {}", i, string)?;
+                fields.push(format!("{}msgstr[{}] {}", prefix, i, string));
             } else {
-                writeln!(f, "msgstr {}", string)?;
+                fields.push(format!("{}msgstr {}", prefix, string));
+            }
+        }
+
+        let mut wrote_references = false;
+        for i in 0..=fields.len() {
+            for comment in &self.comments {
+                let position = if options.faithful {
+                    comment.fields_before.min(fields.len())
+                } else {
+                    0
+                };
+                if position != i || !options.comment_kinds.contains(&comment.kind) {
+                    continue;
+                }
+                if options.fold_references && comment.kind == PotCommentKind::Reference {
+                    if !wrote_references {
+                        let references: Vec<&str> = self.comments.iter()
+                            .filter(|c| c.kind == PotCommentKind::Reference)
+                            .flat_map(|c| c.content.split_whitespace())
+                            .collect();
+                        writeln!(f, "#{} {}", PotCommentKind::Reference, references.join(" "))?;
+                        wrote_references = true;
+                    }
+                    continue;
+                }
+                if self.obsolete && comment.kind == PotCommentKind::Previous {
+                    writeln!(f, "#~{} {}", comment.kind, comment.content)?;
+                    continue;
+                }
+                writeln!(f, "{}", comment)?;
+            }
+            if let Some(field) = fields.get(i) {
+                writeln!(f, "{}", field)?;
             }
         }
         Ok(())
-	}
-}
+    }
 
-impl PotMessage {
-    pub fn new() -> Self {
-        Default::default()
+    fn extend_span(&mut self, span: Range<usize>) {
+        self.span = Some(match self.span.take() {
+            Some(existing) => existing.start..span.end,
+            None => span,
+        });
+    }
+
+    fn field_count(&self) -> usize {
+        self.context.iter().count() + self.id.iter().count() + self.id_plural.iter().count() + self.strings.len()
+    }
+
+    fn heap_size(&self) -> usize {
+        let option_size = |s: &Option<String>| s.as_ref().map(|s| s.capacity()).unwrap_or_default();
+        self.comments.capacity() * std::mem::size_of::<PotComment>()
+            + self.comments.iter().map(|c| c.content.capacity()).sum::<usize>()
+            + option_size(&self.context)
+            + option_size(&self.id)
+            + option_size(&self.id_plural)
+            + self.strings.capacity() * std::mem::size_of::<String>()
+            + self.strings.iter().map(|s| s.capacity()).sum::<usize>()
+            + self.original.as_ref().map(|o| o.text.capacity()).unwrap_or_default()
+    }
+
+    /// Hashes everything that is written out, to tell whether the message
+    /// changed since `original` was recorded.
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (&self.comments, &self.context, &self.id, &self.id_plural, &self.strings, self.obsolete).hash(&mut hasher);
+        self.comments.iter().for_each(|c| c.fields_before.hash(&mut hasher));
+        hasher.finish()
+    }
+
+    /// Whether `original` can be written as is: the message is unchanged and
+    /// writing it with `options` would not alter its layout.
+    fn can_reuse(&self, original: &OriginalText, options: &WriteOptions) -> bool {
+        let line_endings = match options.line_ending {
+            LineEnding::Lf => !original.text.contains('\r'),
+            LineEnding::Crlf => original.text.matches('\n').count() == original.text.matches("\r\n").count(),
+        };
+        original.fingerprint == self.fingerprint()
+            && line_endings
+            && (options.faithful || self.comments.iter().all(|c| c.fields_before == 0))
+            && self.comments.iter().all(|c| options.comment_kinds.contains(&c.kind))
+            && !(options.fold_references && self.comments.iter().filter(|c| c.kind == PotCommentKind::Reference).count() > 1)
+    }
+
+    /// Records the fingerprint of a fully parsed message.
+    fn sealed(mut self) -> Self {
+        let fingerprint = self.fingerprint();
+        if let Some(ref mut original) = self.original {
+            original.fingerprint = fingerprint;
+        }
+        self
+    }
+
+    fn is_empty(&self) -> bool {
+        self.comments.is_empty() && self.context.is_none() && self.id.is_none() && self.id_plural.is_none() && self.strings.is_empty()
     }
 
     fn is_valid(&self) -> bool {
-        self.id.is_some() && (self.strings.len() == 1 || (self.id_plural.is_some() && self.strings.len() > 1))
+        self.id.is_some() && (self.strings.len() == 1 || (self.id_plural.is_some() && self.strings.len() != 1))
     }
 }
 
 impl fmt::Display for PotComment {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "#{} {}", self.kind, self.content)
+        if self.content.is_empty() {
+            return write!(f, "#{}", self.kind);
+        }
+        write!(f, "#{} {}", self.kind, self.written_content())
 	}
 }
 
@@ -118,14 +1017,31 @@ impl FromStr for PotComment {
         };
         Ok(PotComment{
             content: content.trim_start().to_string(),
-            kind: comment_type
+            kind: comment_type,
+            fields_before: 0,
         })
     }
 }
 
 impl PotComment {
     pub fn is_comment(s: &str) -> bool {
-        !s.is_empty() && &s[0..1] == "#"
+        s.starts_with('#')
+    }
+
+    /// The content as it is written out.
+    fn written_content(&self) -> Cow<'_, str> {
+        if self.kind == PotCommentKind::Flag {
+            // gettext always lists fuzzy first, e.g. `#, fuzzy, c-format`.
+            let mut flags: Vec<&str> = self.content.split(',').map(str::trim).filter(|f| !f.is_empty()).collect();
+            if !flags.is_empty() {
+                if let Some(position) = flags.iter().position(|f| f.eq_ignore_ascii_case("fuzzy")) {
+                    let fuzzy = flags.remove(position);
+                    flags.insert(0, fuzzy);
+                }
+                return Cow::Owned(flags.join(", "));
+            }
+        }
+        Cow::Borrowed(&self.content)
     }
 }
 
@@ -136,7 +1052,7 @@ impl fmt::Display for PotCommentKind {
             PotCommentKind::Extracted => ".",
             PotCommentKind::Flag => ",",
             PotCommentKind::Previous => "|",
-            _ => "",
+            PotCommentKind::Translator => "",
 		})
 	}
 }
@@ -147,125 +1063,1373 @@ impl FromStr for PotCommentKind {
         if !PotComment::is_comment(s) {
             return Err(())
         }
-        Ok(match &s[1..2] {
-            ":" => PotCommentKind::Reference,
-            "." => PotCommentKind::Extracted,
-            "," => PotCommentKind::Flag,
-            "|" => PotCommentKind::Previous,
+        Ok(match s.get(1..2) {
+            Some(":") => PotCommentKind::Reference,
+            Some(".") => PotCommentKind::Extracted,
+            Some(",") => PotCommentKind::Flag,
+            Some("|") => PotCommentKind::Previous,
             _ => PotCommentKind::Translator,
 		})
     }
 }
 
-impl Default for Pot {
-    fn default() -> Self {
-        Pot {
-            messages: Vec::new()
-        }
-    }
-}
-
 impl Pot {
     pub fn new() -> Self {
         Default::default()
     }
 
-    pub fn read<R: Read>(reader: &mut R) -> Pot {
-        let f = BufReader::new(reader);
+    /// Estimates the heap bytes held by the catalog, based on the capacity of
+    /// every string and vector it owns.
+    pub fn heap_size(&self) -> usize {
+        self.messages.capacity() * std::mem::size_of::<PotMessage>()
+            + self.messages.iter().map(|m| m.heap_size()).sum::<usize>()
+            + self.obsolete_lines.capacity() * std::mem::size_of::<String>()
+            + self.obsolete_lines.iter().map(|s| s.capacity()).sum::<usize>()
+            + self.trailing_comments.capacity() * std::mem::size_of::<String>()
+            + self.trailing_comments.iter().map(|s| s.capacity()).sum::<usize>()
+    }
+
+    /// Creates an empty catalog whose header is filled in for `language`,
+    /// including its `Plural-Forms` rule when the language is known.
+    pub fn with_header(language: &str) -> Pot {
         let mut pot = Pot::new();
-        let mut message = PotMessage::new();
-        let mut command = PotCommand::new();
+        pot.set_header_field("Language", language);
+        pot.set_header_field("MIME-Version", "1.0");
+        pot.set_header_field("Content-Type", "text/plain; charset=UTF-8");
+        pot.set_header_field("Content-Transfer-Encoding", "8bit");
+        pot.set_language(language);
+        pot
+    }
 
-        let re = Regex::new(r#"^"(.*?[^\\])?"$"#).unwrap();
+    pub fn header(&self) -> Option<&PotMessage> {
+        self.messages.iter().find(|m| m.is_header() && !m.obsolete)
+    }
 
-        for line in f.lines() {
-            let s = line.unwrap();
-            if let Ok(comment) = s.parse::<PotComment>() {
-                if message.is_valid() {
-                    pot.messages.push(message);
-                    message = PotMessage::new();
-                }
-                message.comments.push(comment);
-            } else if let Ok(cmd) = s.parse::<PotCommand>() {
-                if !cmd.can_apply(&mut message) {
-                    pot.messages.push(message);
-                    message = PotMessage::new();
-                }
-                cmd.apply(&mut message);
-                command = cmd;
-            } else if let Some(caps) = re.captures(&s) {
-                let s_msg = caps.get(1).and_then(|m| Some(m.as_str())).unwrap_or_default();
-                command.value.push_str(unescape(s_msg).unwrap().as_ref());
-                command.force_apply(&mut message);
-            }
+    /// Returns the header entry, inserting an empty one at the start of the
+    /// catalog if there isn't a live one yet.
+    pub fn ensure_header(&mut self) -> &mut PotMessage {
+        let index = match self.messages.iter().position(|m| m.is_header() && !m.obsolete) {
+            Some(index) => index,
+            None => {
+                let mut header = PotMessage::new();
+                header.id = Some(String::new());
+                header.strings.push(String::new());
+                self.messages.insert(0, header);
+                0
+            },
+        };
+        &mut self.messages[index]
+    }
+
+    /// Sets the `name` field of the header, replacing any existing value.
+    pub fn set_header_field(&mut self, name: &str, value: &str) {
+        let header = self.ensure_header();
+        if header.strings.is_empty() {
+            header.strings.push(String::new());
+        }
+        let mut lines: Vec<String> = header.strings[0].lines().map(|l| l.to_string()).collect();
+        let field = format!("{}: {}", name, value);
+        let position = lines.iter().position(|line| {
+            line.split_once(':').map(|(key, _)| key.trim().eq_ignore_ascii_case(name)).unwrap_or_default()
+        });
+        match position {
+            Some(i) => lines[i] = field,
+            None => lines.push(field),
         }
+        header.strings[0] = lines.iter().map(|l| format!("{}\n", l)).collect();
+    }
 
-        if pot.messages.is_empty() || pot.messages.last().unwrap().id.as_ref() != message.id.as_ref() {
-            pot.messages.push(message);
+    /// Sets the header's `Language` field, along with its `Plural-Forms` when
+    /// the language is known.
+    pub fn set_language(&mut self, language: &str) {
+        self.set_header_field("Language", language);
+        if let Some(plural_forms) = plural_forms_for(language) {
+            self.set_header_field("Plural-Forms", plural_forms);
         }
+    }
 
-        pot
+    /// Guesses whether this is a `.pot` template rather than a translation:
+    /// its header is still fuzzy, or none of its entries are translated.
+    pub fn is_template(&self) -> bool {
+        self.header().map(|h| h.is_fuzzy()).unwrap_or_default()
+            || self.messages.iter().filter(|m| !m.is_header() && !m.obsolete).all(|m| !m.is_translated())
     }
 
-    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
-        for (i, message) in self.messages.iter().enumerate() {
-            writer.write(format!("{}", message).as_ref())?;
-            if i < self.messages.len() - 1 {
-                writer.write("\n".as_ref())?;
+    /// Counts how many live messages carry each flag.
+    pub fn flag_histogram(&self) -> BTreeMap<String, usize> {
+        let mut histogram = BTreeMap::new();
+        for message in self.messages.iter().filter(|m| !m.obsolete) {
+            for flag in message.flags().into_iter().collect::<BTreeSet<_>>() {
+                *histogram.entry(flag.to_string()).or_insert(0) += 1;
             }
         }
-        Ok(())
+        histogram
     }
-}
 
-impl FromStr for PotCommand {
-    type Err = ();
-    fn from_str(s: &str) -> Result<PotCommand, Self::Err> {
-        let re = Regex::new(r#"^(?P<cmd>[a-z_]+)(?:\[(?P<idx>[0-9]+)\])? "(?P<val>.*?[^\\])?""#).unwrap();
-        if let Some(caps) = re.captures(s) {
-            let cmd = caps.name("cmd").and_then(|m| Some(m.as_str())).unwrap_or_default();
-            let idx = caps.name("idx").and_then(|m| Some(m.as_str())).unwrap_or_default();
-            let val = caps.name("val").and_then(|m| Some(m.as_str())).unwrap_or_default();
+    /// Counts the distinct `path:line` references across the live entries.
+    pub fn reference_count(&self) -> usize {
+        self.entries().flat_map(|m| m.references()).collect::<BTreeSet<_>>().len()
+    }
 
-            let mut cmd = PotCommand{
-                key: cmd.to_string(),
-                index: None,
-                value: unescape(val).unwrap(),
-            };
+    /// Returns the distinct source files referenced by live entries, sorted.
+    pub fn referenced_files(&self) -> Vec<&str> {
+        self.entries()
+            .flat_map(|m| m.references())
+            .map(|(path, _)| path)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
 
-            if !idx.is_empty() {
-                cmd.index = Some(idx.parse::<usize>().unwrap());
+    /// Maps each referenced source file to `(translated, total)` counts of
+    /// the live entries referencing it. Fuzzy entries count as untranslated.
+    pub fn coverage_by_file(&self) -> BTreeMap<String, (usize, usize)> {
+        let mut coverage = BTreeMap::new();
+        for message in self.entries() {
+            let translated = message.is_translated() && !message.is_fuzzy();
+            for path in message.references().into_iter().map(|(path, _)| path).collect::<BTreeSet<_>>() {
+                let counts: &mut (usize, usize) = coverage.entry(path.to_string()).or_default();
+                counts.0 += translated as usize;
+                counts.1 += 1;
             }
-
-            return Ok(cmd);
         }
-        Err(())
+        coverage
+    }
+
+    /// Returns the indices of live entries that match `predicate` but don't
+    /// carry `flag`, e.g. strings containing `%` without `c-format`.
+    pub fn entries_missing_flag(&self, flag: &str, predicate: impl Fn(&PotMessage) -> bool) -> Vec<usize> {
+        self.messages.iter().enumerate()
+            .filter(|(_, m)| !m.obsolete && !m.is_header() && predicate(m) && !m.flags().contains(&flag))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Sorts the entries by `key`, keeping the header first.
+    pub fn sort(&mut self, key: SortKey) {
+        self.sort_with(key, &SortOptions::default())
+    }
+
+    /// Like `sort`, with `options` controlling which entries stay pinned.
+    pub fn sort_with(&mut self, key: SortKey, options: &SortOptions) {
+        let header = self.messages.iter().position(|m| m.is_header()).map(|i| self.messages.remove(i));
+        match key {
+            SortKey::Msgid => self.messages.sort_by(|a, b| (&a.id, &a.context).cmp(&(&b.id, &b.context))),
+            SortKey::Reference => self.messages.sort_by_cached_key(|m| {
+                let reference = m.references().first().map(|(path, line)| (path.to_string(), *line));
+                (reference.is_none(), reference)
+            }),
+        }
+        if options.obsolete_last {
+            // Stable, so both groups keep the order just established.
+            self.messages.sort_by_key(|m| m.obsolete);
+        }
+        if let Some(header) = header {
+            self.messages.insert(0, header);
+        }
+    }
+
+    /// Iterates over the live entries, skipping the header and obsolete
+    /// entries.
+    pub fn entries(&self) -> impl Iterator<Item = &PotMessage> {
+        self.messages.iter().filter(|m| !m.is_header() && !m.obsolete)
+    }
+
+    /// Iterates over the live entries that still lack a translation.
+    pub fn untranslated(&self) -> impl Iterator<Item = &PotMessage> {
+        self.entries().filter(|m| !m.is_translated())
+    }
+
+    /// Returns up to `n` untranslated entries with the most source words,
+    /// largest first.
+    pub fn largest_untranslated(&self, n: usize) -> Vec<&PotMessage> {
+        let mut untranslated: Vec<&PotMessage> = self.untranslated().collect();
+        untranslated.sort_by_key(|m| std::cmp::Reverse(m.word_count()));
+        untranslated.truncate(n);
+        untranslated
+    }
+
+    /// Splits the untranslated entries into `n` catalogs of roughly equal word
+    /// count, each with a copy of the header, to share work among translators.
+    /// An `n` of 0 is treated as 1, so no entry is ever left out.
+    pub fn chunks(&self, n: usize) -> Vec<Pot> {
+        let n = n.max(1);
+        let mut untranslated: Vec<(usize, &PotMessage)> = self.untranslated().enumerate().collect();
+        untranslated.sort_by_key(|(_, m)| std::cmp::Reverse(m.word_count()));
+        let mut assigned: Vec<(usize, Vec<(usize, &PotMessage)>)> = (0..n).map(|_| (0, Vec::new())).collect();
+        for (i, message) in untranslated {
+            if let Some(chunk) = assigned.iter_mut().min_by_key(|(words, _)| *words) {
+                chunk.0 += message.word_count();
+                chunk.1.push((i, message));
+            }
+        }
+        assigned.into_iter().map(|(_, mut messages)| {
+            messages.sort_by_key(|(i, _)| *i);
+            let mut pot = Pot::new();
+            if let Some(header) = self.header() {
+                pot.messages.push(header.clone());
+            }
+            pot.messages.extend(messages.into_iter().map(|(_, m)| m.clone()));
+            pot
+        }).collect()
+    }
+
+    pub fn entries_including_obsolete(&self) -> impl Iterator<Item = &PotMessage> {
+        self.messages.iter().filter(|m| !m.is_header())
+    }
+
+    /// Iterates over the same entries as `entries_including_obsolete`, in
+    /// reverse document order, so an obsolete tail comes first. The header
+    /// is still excluded.
+    pub fn entries_rev(&self) -> impl Iterator<Item = &PotMessage> {
+        self.messages.iter().rev().filter(|m| !m.is_header())
+    }
+
+    /// Returns the value of the `name` field in the header, e.g.
+    /// `header_field("Language")`.
+    pub fn header_field(&self, name: &str) -> Option<&str> {
+        self.header()?.strings.first()?.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim().eq_ignore_ascii_case(name) { Some(value.trim()) } else { None }
+        })
+    }
+
+    /// Returns the `nplurals` count from the header's `Plural-Forms` field.
+    pub fn nplurals(&self) -> Option<usize> {
+        self.header_field("Plural-Forms")?.split(';').find_map(|part| {
+            let (key, value) = part.split_once('=')?;
+            if key.trim() == "nplurals" { value.trim().parse().ok() } else { None }
+        })
+    }
+
+    /// Compiles the header's `plural=` expression into a function mapping a
+    /// count to a plural form index, so it only needs parsing once.
+    pub fn plural_selector(&self) -> Result<Box<dyn Fn(u64) -> usize + Send + Sync>, PluralParseError> {
+        plural::compile(self.plural_expression().ok_or(PluralParseError::Missing)?)
+    }
+
+    fn plural_expression(&self) -> Option<&str> {
+        self.header_field("Plural-Forms")?.split(';').find_map(|part| {
+            let (key, value) = part.split_once('=')?;
+            if key.trim() == "plural" { Some(value) } else { None }
+        })
+    }
+
+    /// Builds a translation memory from the translated, non-fuzzy entries,
+    /// mapping each msgid and msgid_plural to its translation.
+    pub fn to_memory(&self) -> TranslationMemory {
+        TranslationMemory::new(self)
+    }
+
+    /// Pairs each live plural entry with the form its plural rule selects for
+    /// `n`, or `""` when that form is missing. Catalogs without a usable
+    /// `plural=` expression fall back to the English rule.
+    pub fn plural_preview(&self, n: u64) -> impl Iterator<Item = (&PotMessage, &str)> {
+        let index = match self.plural_selector() {
+            Ok(selector) => selector(n),
+            Err(_) => (n != 1) as usize,
+        };
+        self.entries()
+            .filter(|m| m.id_plural.is_some())
+            .map(move |m| (m, m.strings.get(index).map(String::as_str).unwrap_or("")))
+    }
+
+    /// Converts the catalog into an immutable `CompiledCatalog` optimised for
+    /// runtime lookups.
+    pub fn compile(self) -> CompiledCatalog {
+        CompiledCatalog::new(self)
+    }
+
+    /// Returns the indices of plural entries whose number of `msgstr[n]` forms
+    /// doesn't match the header's `nplurals`. Entries without any forms are
+    /// fine, as they are written with `nplurals` empty ones.
+    pub fn plural_consistency(&self) -> Vec<usize> {
+        let nplurals = match self.nplurals() {
+            Some(n) => n,
+            None => return Vec::new(),
+        };
+        self.messages.iter().enumerate()
+            .filter(|(_, m)| !m.obsolete && m.id_plural.is_some() && !m.strings.is_empty() && m.strings.len() != nplurals)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Pads or truncates the forms of live plural entries to the header's
+    /// `nplurals`, returning how many entries changed. Only empty trailing
+    /// forms are dropped, so entries with extra translated forms stay long.
+    pub fn fix_plural_forms_count(&mut self) -> usize {
+        let nplurals = match self.nplurals() {
+            Some(n) => n,
+            None => return 0,
+        };
+        let mut adjusted = 0;
+        for message in self.messages.iter_mut().filter(|m| !m.obsolete && m.id_plural.is_some()) {
+            let before = message.strings.len();
+            if before < nplurals {
+                message.strings.resize(nplurals, String::new());
+            }
+            while message.strings.len() > nplurals && message.strings.last().map(|s| s.is_empty()).unwrap_or_default() {
+                message.strings.pop();
+            }
+            if message.strings.len() != before {
+                adjusted += 1;
+            }
+        }
+        adjusted
+    }
+
+    /// Returns the indices of live entries with a translation more than `ratio`
+    /// times as long (in characters) as its source text. Plural forms after
+    /// the first are measured against `msgid_plural`.
+    pub fn length_outliers(&self, ratio: f64) -> Vec<usize> {
+        self.messages.iter().enumerate()
+            .filter(|(_, m)| !m.is_header() && !m.obsolete)
+            .filter(|(_, m)| m.strings.iter().enumerate().any(|(i, s)| {
+                let source = match m.id_plural {
+                    Some(ref id_plural) if i > 0 => id_plural.as_str(),
+                    _ => m.id.as_deref().unwrap_or_default(),
+                };
+                s.chars().count() as f64 > source.chars().count() as f64 * ratio
+            }))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        self.validate_with(&ValidateOptions::default())
+    }
+
+    pub fn validate_with(&self, options: &ValidateOptions) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let tag = Regex::new(r"<(/?)\s*([A-Za-z][A-Za-z0-9:-]*)[^<>]*?(/?)>").unwrap();
+        for (index, message) in self.messages.iter().enumerate().filter(|(_, m)| !m.obsolete) {
+            let mut seen = BTreeSet::new();
+            for c in message.strings.iter().flat_map(|s| s.chars()) {
+                if c.is_control() && !options.allowed_control_characters.contains(&c) && seen.insert(c) {
+                    issues.push(ValidationIssue::ControlCharacter { index, codepoint: c as u32 });
+                }
+            }
+            if let Some(ref id_plural) = message.id_plural {
+                if id_plural.is_empty() || message.id.as_deref().unwrap_or_default().is_empty() {
+                    issues.push(ValidationIssue::EmptyPluralSource { index });
+                }
+            }
+            if !message.is_header() {
+                let translations = || message.strings.iter().enumerate().filter(|(_, s)| !s.is_empty()).map(|(i, s)| {
+                    let source = match message.id_plural {
+                        Some(ref id_plural) if i > 0 => id_plural.as_str(),
+                        _ => message.id.as_deref().unwrap_or_default(),
+                    };
+                    (source, s)
+                });
+                if translations().any(|(source, s)| source.starts_with('\n') != s.starts_with('\n') || source.ends_with('\n') != s.ends_with('\n')) {
+                    issues.push(ValidationIssue::NewlineMismatch { index });
+                }
+                if let Some(marker) = options.accelerator {
+                    if translations().any(|(source, s)| count_accelerators(source, marker) != count_accelerators(s, marker)) {
+                        issues.push(ValidationIssue::AcceleratorMismatch { index });
+                    }
+                }
+                if options.markup && translations().any(|(source, s)| {
+                    markup_tags(&tag, source, &options.ignored_tags) != markup_tags(&tag, s, &options.ignored_tags)
+                }) {
+                    issues.push(ValidationIssue::MarkupMismatch { index });
+                }
+            }
+        }
+        if options.unique_msgids {
+            let mut by_id: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+            for (index, message) in self.messages.iter().enumerate().filter(|(_, m)| !m.obsolete && !m.is_header()) {
+                if let Some(ref id) = message.id {
+                    by_id.entry(id).or_default().push(index);
+                }
+            }
+            for (id, indices) in by_id {
+                let contexts: BTreeSet<_> = indices.iter().map(|&i| &self.messages[i].context).collect();
+                if contexts.len() > 1 {
+                    issues.push(ValidationIssue::DuplicateMsgidAcrossContexts { id: id.to_string(), indices });
+                }
+            }
+        }
+        issues
+    }
+
+    /// Checks the `MIME-Version`, `Content-Type` and
+    /// `Content-Transfer-Encoding` header fields the way `msgfmt` does.
+    pub fn validate_header_mime(&self) -> Vec<HeaderIssue> {
+        const CHARSETS: &[&str] = &[
+            "ASCII", "UTF-8", "ISO-8859-1", "ISO-8859-2", "ISO-8859-3", "ISO-8859-4", "ISO-8859-5",
+            "ISO-8859-6", "ISO-8859-7", "ISO-8859-8", "ISO-8859-9", "ISO-8859-13", "ISO-8859-14",
+            "ISO-8859-15", "KOI8-R", "KOI8-U", "KOI8-T", "CP850", "CP866", "CP874", "CP932", "CP949",
+            "CP950", "CP1250", "CP1251", "CP1252", "CP1253", "CP1254", "CP1255", "CP1256", "CP1257",
+            "GB2312", "EUC-JP", "EUC-KR", "EUC-TW", "BIG5", "BIG5-HKSCS", "GBK", "GB18030", "SHIFT_JIS",
+            "JOHAB", "TIS-620", "VISCII", "GEORGIAN-PS",
+        ];
+        let mut issues = Vec::new();
+        let mut check = |field: &str, suggestion: &str, is_valid: &dyn Fn(&str) -> bool| {
+            match self.header_field(field) {
+                None => issues.push(HeaderIssue::Missing { field: field.to_string(), suggestion: suggestion.to_string() }),
+                Some(value) if !is_valid(value) => issues.push(HeaderIssue::Malformed {
+                    field: field.to_string(),
+                    value: value.to_string(),
+                    suggestion: suggestion.to_string(),
+                }),
+                Some(_) => (),
+            }
+        };
+        check("MIME-Version", "1.0", &|value| value == "1.0");
+        check("Content-Type", "text/plain; charset=UTF-8", &|value| {
+            let mut parts = value.split(';').map(str::trim);
+            parts.next().map(|t| t.eq_ignore_ascii_case("text/plain")).unwrap_or_default() && parts.any(|part| {
+                match part.split_once('=') {
+                    Some((key, charset)) => key.trim().eq_ignore_ascii_case("charset")
+                        && CHARSETS.iter().any(|c| c.eq_ignore_ascii_case(charset.trim())),
+                    None => false,
+                }
+            })
+        });
+        check("Content-Transfer-Encoding", "8bit", &|value| value.eq_ignore_ascii_case("8bit"));
+        issues
+    }
+
+    /// Returns the indices of live entries with a non-empty translation that
+    /// is identical to its source, which often means a placeholder was left
+    /// in. Only a hint, since names and symbols can legitimately match.
+    pub fn untranslated_identical(&self) -> Vec<usize> {
+        self.messages.iter().enumerate()
+            .filter(|(_, m)| !m.obsolete && !m.is_header())
+            .filter(|(_, m)| m.strings.iter().enumerate().any(|(i, s)| {
+                let source = match m.id_plural {
+                    Some(ref id_plural) if i > 0 => Some(id_plural.as_str()),
+                    _ => m.id.as_deref(),
+                };
+                !s.is_empty() && source == Some(s.as_str())
+            }))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Groups the indices of live entries sharing the same non-empty
+    /// translation, for spotting strings that could share one entry.
+    pub fn common_translations(&self) -> Vec<(String, Vec<usize>)> {
+        let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (index, message) in self.messages.iter().enumerate().filter(|(_, m)| !m.is_header() && !m.obsolete) {
+            if let Some(translation) = message.strings.first().filter(|s| !s.is_empty()) {
+                groups.entry(translation).or_default().push(index);
+            }
+        }
+        groups.into_iter()
+            .filter(|(_, indices)| indices.len() > 1)
+            .map(|(translation, indices)| (translation.to_string(), indices))
+            .collect()
+    }
+
+    /// Converts curly quotes in translations to straight ones, returning how
+    /// many entries changed. Opt-in, since some languages require their own
+    /// quote style; the header is skipped.
+    pub fn normalize_quotes(&mut self) -> usize {
+        self.normalize_quotes_with(QuoteStyle::Straight)
+    }
+
+    /// Like `normalize_quotes`, converting to the given `style`.
+    pub fn normalize_quotes_with(&mut self, style: QuoteStyle) -> usize {
+        let mut changed = 0;
+        for message in self.messages.iter_mut().filter(|m| !m.is_header()) {
+            let mut modified = false;
+            for string in message.strings.iter_mut() {
+                let normalized = style.apply(string);
+                if normalized != *string {
+                    *string = normalized;
+                    modified = true;
+                }
+            }
+            changed += modified as usize;
+        }
+        changed
+    }
+
+    /// Replaces every occurrence of `from` with `to` in all translations,
+    /// skipping the header, and returns the number of replacements made.
+    pub fn replace_in_translations(&mut self, from: &str, to: &str) -> usize {
+        self.replace_in_translations_with(from, to, false)
+    }
+
+    /// Like `replace_in_translations`, optionally marking every changed entry
+    /// fuzzy so it gets reviewed.
+    pub fn replace_in_translations_with(&mut self, from: &str, to: &str, mark_fuzzy: bool) -> usize {
+        if from.is_empty() {
+            return 0;
+        }
+        let mut total = 0;
+        for message in self.messages.iter_mut().filter(|m| !m.is_header()) {
+            let mut count = 0;
+            for string in message.strings.iter_mut() {
+                let matches = string.matches(from).count();
+                if matches > 0 {
+                    *string = string.replace(from, to);
+                    count += matches;
+                }
+            }
+            if count > 0 && mark_fuzzy {
+                message.mark_fuzzy();
+            }
+            total += count;
+        }
+        total
+    }
+
+    pub fn find(&self, context: Option<&str>, id: &str) -> Option<&PotMessage> {
+        self.messages.iter().find(|m| !m.obsolete && m.context.as_deref() == context && m.id.as_deref() == Some(id))
+    }
+
+    /// Like `find`, but falls back to matching msgids with their whitespace
+    /// normalized, so `"Save  file "` finds `"Save file"`. The exact match is
+    /// still preferred and the stored msgid remains the canonical key.
+    pub fn find_normalized(&self, context: Option<&str>, id: &str) -> Option<&PotMessage> {
+        self.find(context, id).or_else(|| {
+            let id = normalize_whitespace(id);
+            self.messages.iter().find(|m| {
+                !m.obsolete && m.context.as_deref() == context && m.id.as_deref().map(normalize_whitespace).as_ref() == Some(&id)
+            })
+        })
+    }
+
+    /// Like `find`, but falls back to an obsolete entry when no live one
+    /// matches.
+    pub fn find_including_obsolete(&self, context: Option<&str>, id: &str) -> Option<&PotMessage> {
+        self.find(context, id).or_else(|| {
+            self.messages.iter().find(|m| m.context.as_deref() == context && m.id.as_deref() == Some(id))
+        })
+    }
+
+    /// Looks up the translation of `msgid`, falling back to `msgid` itself
+    /// when the entry is missing, untranslated, fuzzy or obsolete.
+    pub fn gettext<'a>(&'a self, msgid: &'a str) -> &'a str {
+        self.gettext_with(msgid, Fallback::Msgid)
+    }
+
+    /// Looks up the plural translation of `msgid` for count `n`, using the
+    /// header's plural rule. Falls back to `msgid` or `msgid_plural` like
+    /// `gettext` does. The rule is compiled on first use and kept until the
+    /// header's `Plural-Forms` changes.
+    pub fn ngettext<'a>(&'a self, msgid: &'a str, msgid_plural: &'a str, n: u64) -> &'a str {
+        let index = match self.plural_expression().and_then(|expression| self.plural.get(expression)) {
+            Some(selector) => selector(n),
+            None => (n != 1) as usize,
+        };
+        match self.find(None, msgid) {
+            Some(m) if !m.is_fuzzy() && m.strings.get(index).map(|s| !s.is_empty()).unwrap_or_default() => &m.strings[index],
+            _ if n == 1 => msgid,
+            _ => msgid_plural,
+        }
+    }
+
+    pub fn gettext_with<'a>(&'a self, msgid: &'a str, fallback: Fallback) -> &'a str {
+        match self.find(None, msgid) {
+            Some(m) if !m.is_fuzzy() && m.strings.first().map(|s| !s.is_empty()).unwrap_or_default() => &m.strings[0],
+            _ => match fallback {
+                Fallback::Msgid => msgid,
+                Fallback::Empty => "",
+                Fallback::Marked => "[[missing]]",
+            },
+        }
+    }
+
+    /// Translates `msgid` and substitutes its `%s` and `%1$s` placeholders
+    /// with `args`. Placeholders without a matching argument are left as-is
+    /// and extra arguments are ignored; use `try_format` to reject both.
+    pub fn format(&self, msgid: &str, args: &[&str]) -> String {
+        interpolate(self.gettext(msgid), args, false).unwrap()
+    }
+
+    pub fn try_format(&self, msgid: &str, args: &[&str]) -> Result<String, FormatError> {
+        interpolate(self.gettext(msgid), args, true)
+    }
+
+    /// Iterates over the lookup key and translation of every live entry that
+    /// is translated and not fuzzy.
+    pub fn pairs(&self) -> impl Iterator<Item = (String, &str)> {
+        self.entries()
+            .filter(|m| m.is_translated() && !m.is_fuzzy())
+            .map(|m| (m.key(), m.strings[0].as_str()))
+    }
+
+    /// Like `pairs`, but with each translation escaped for embedding in
+    /// another format.
+    pub fn escaped_pairs(&self, escape: EscapeStyle) -> impl Iterator<Item = (String, String)> + '_ {
+        self.pairs().map(move |(key, value)| (key, escape.escape(value)))
+    }
+
+    /// Describes each entry on one line, e.g. `[fuzzy] Menu/Open => Öffnen`,
+    /// for a quick look at a catalog in the terminal.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        for message in self.entries_including_obsolete() {
+            if message.obsolete {
+                summary.push_str("[obsolete] ");
+            }
+            if message.is_fuzzy() {
+                summary.push_str("[fuzzy] ");
+            } else if !message.is_translated() {
+                summary.push_str("[untranslated] ");
+            }
+            if let Some(ref ctx) = message.context {
+                write!(summary, "{}/", abbreviate(ctx, 20)).unwrap();
+            }
+            let id = message.id.as_deref().unwrap_or_default();
+            let translation = message.strings.first().map(|s| s.as_str()).unwrap_or_default();
+            writeln!(summary, "{} => {}", abbreviate(id, 40), abbreviate(translation, 40)).unwrap();
+        }
+        summary
+    }
+
+    /// Returns the fraction of live entries that are fully translated and not
+    /// fuzzy, from 0.0 to 1.0. A plural entry needs every form the header's
+    /// `nplurals` asks for. A catalog with no entries counts as complete.
+    pub fn completeness(&self) -> f64 {
+        let stats = self.stats();
+        if stats.total == 0 {
+            return 1.0;
+        }
+        stats.translated as f64 / stats.total as f64
+    }
+
+    pub fn stats(&self) -> PotStats {
+        let mut stats = PotStats::default();
+        let nplurals = self.nplurals();
+        for message in self.entries_including_obsolete() {
+            if message.obsolete {
+                stats.obsolete += 1;
+                continue;
+            }
+            stats.total += 1;
+            if message.is_fuzzy() {
+                stats.fuzzy += 1;
+            } else if message.is_fully_translated(nplurals) {
+                stats.translated += 1;
+            } else {
+                stats.untranslated += 1;
+            }
+        }
+        stats
+    }
+
+    /// Returns the message whose source span contains byte `offset` of the
+    /// input it was read from.
+    pub fn entry_at_offset(&self, offset: usize) -> Option<&PotMessage> {
+        self.messages.iter().find(|m| m.span.as_ref().map(|span| span.contains(&offset)).unwrap_or_default())
+    }
+
+    pub fn entries_referencing<'a>(&'a self, path: &'a str) -> impl Iterator<Item = &'a PotMessage> {
+        self.entries_referencing_with(path, PathMatch::Exact)
+    }
+
+    pub fn entries_referencing_with<'a>(&'a self, path: &'a str, path_match: PathMatch) -> impl Iterator<Item = &'a PotMessage> {
+        self.messages.iter().filter(move |m| {
+            !m.obsolete && m.references().iter().any(|(p, _)| match path_match {
+                PathMatch::Exact => *p == path,
+                PathMatch::Suffix => *p == path || p.ends_with(&format!("/{}", path)),
+            })
+        })
+    }
+
+    /// Whether both catalogs have exactly the same live entries, as compared
+    /// by `PotMessage::key`, regardless of their translations.
+    pub fn same_keys(&self, other: &Pot) -> bool {
+        let (only_in_self, only_in_other) = self.key_set_diff(other);
+        only_in_self.is_empty() && only_in_other.is_empty()
+    }
+
+    /// Returns the sorted keys of live entries found only in `self` and only
+    /// in `other`.
+    pub fn key_set_diff(&self, other: &Pot) -> (Vec<String>, Vec<String>) {
+        let ours: BTreeSet<String> = self.entries().map(|m| m.key()).collect();
+        let theirs: BTreeSet<String> = other.entries().map(|m| m.key()).collect();
+        (ours.difference(&theirs).cloned().collect(), theirs.difference(&ours).cloned().collect())
+    }
+
+    /// Builds a catalog holding the header plus the live entries matching the
+    /// given `(context, msgid)` keys, in their original order. Keys with no
+    /// entry are skipped.
+    pub fn subset<I: IntoIterator<Item = (Option<String>, String)>>(&self, keys: I) -> Pot {
+        let keys: BTreeSet<String> = keys.into_iter().map(|(context, id)| match context {
+            Some(context) => format!("{}\u{4}{}", context, id),
+            None => id,
+        }).collect();
+        let mut pot = Pot::new();
+        if let Some(header) = self.header() {
+            pot.messages.push(header.clone());
+        }
+        pot.messages.extend(self.entries().filter(|m| keys.contains(&m.key())).cloned());
+        pot
+    }
+
+    /// Builds a catalog holding the header plus every live fuzzy entry, for a
+    /// review pass.
+    pub fn fuzzy_only(&self) -> Pot {
+        let mut pot = Pot::new();
+        if let Some(header) = self.header() {
+            pot.messages.push(header.clone());
+        }
+        pot.messages.extend(self.entries().filter(|m| m.is_fuzzy()).cloned());
+        pot
+    }
+
+    /// Returns the sorted keys translated in `self` but missing, untranslated
+    /// or fuzzy in `other`, e.g. to compare two languages of one template.
+    pub fn keys_translated_only_in(&self, other: &Pot) -> Vec<String> {
+        let done = |pot: &Pot| -> BTreeSet<String> {
+            pot.entries().filter(|m| m.is_translated() && !m.is_fuzzy()).map(|m| m.key()).collect()
+        };
+        done(self).difference(&done(other)).cloned().collect()
+    }
+
+    /// Three-way merges two catalogs derived from `base`, entry by entry.
+    /// A change made on only one side is taken; entries changed differently on
+    /// both sides are reported as conflicts and kept (ours if present) marked
+    /// fuzzy. The header is taken from `ours`, or from `theirs` if ours has
+    /// none. The result follows the order of `ours`, with entries added by
+    /// `theirs` at the end.
+    pub fn merge3(base: &Pot, ours: &Pot, theirs: &Pot) -> (Pot, Vec<Conflict>) {
+        Pot::merge3_with(base, ours, theirs, &MergeOptions::default())
+    }
+
+    /// Like `merge3`, with `options` controlling how entries are matched.
+    pub fn merge3_with(base: &Pot, ours: &Pot, theirs: &Pot, options: &MergeOptions) -> (Pot, Vec<Conflict>) {
+        // Live entries by key, plus by whitespace-normalized key when asked
+        // to, keeping the first match like `find` and `find_normalized`.
+        type Index<'a> = (HashMap<(Option<&'a str>, &'a str), &'a PotMessage>, HashMap<(Option<&'a str>, String), &'a PotMessage>);
+        fn index(pot: &Pot, normalize: bool) -> Index<'_> {
+            let mut index: Index<'_> = (HashMap::new(), HashMap::new());
+            for message in pot.messages.iter().filter(|m| !m.obsolete) {
+                if let Some(ref id) = message.id {
+                    let context = message.context.as_deref();
+                    index.0.entry((context, id)).or_insert(message);
+                    if normalize {
+                        index.1.entry((context, normalize_whitespace(id))).or_insert(message);
+                    }
+                }
+            }
+            index
+        }
+        fn find<'a>(index: &Index<'a>, message: &PotMessage) -> Option<&'a PotMessage> {
+            let (context, id) = (message.context.as_deref(), message.id.as_deref().unwrap_or_default());
+            index.0.get(&(context, id)).or_else(|| index.1.get(&(context, normalize_whitespace(id)))).copied()
+        }
+        let normalize = options.normalize_whitespace;
+        let (base_keys, ours_keys, theirs_keys) = (index(base, normalize), index(ours, normalize), index(theirs, normalize));
+        let mut merged = Pot::new();
+        let mut conflicts = Vec::new();
+        let mut resolve = |b: Option<&PotMessage>, o: Option<&PotMessage>, t: Option<&PotMessage>| {
+            if o == t || t == b {
+                o.cloned()
+            } else if o == b {
+                t.cloned()
+            } else if o.map(|m| &m.strings) == t.map(|m| &m.strings) {
+                o.cloned()
+            } else if o.is_some() && t.is_some() && t.map(|m| &m.strings) == b.map(|m| &m.strings) {
+                // Both sides changed the entry but only ours changed the
+                // translation, e.g. theirs just reformatted the msgid.
+                o.cloned()
+            } else if o.is_some() && t.is_some() && o.map(|m| &m.strings) == b.map(|m| &m.strings) {
+                t.cloned()
+            } else {
+                let mut message = o.or(t).cloned().unwrap_or_default();
+                conflicts.push(Conflict {
+                    context: message.context.clone(),
+                    id: message.id.clone().unwrap_or_default(),
+                    base: b.map(|m| m.strings.clone()),
+                    ours: o.map(|m| m.strings.clone()),
+                    theirs: t.map(|m| m.strings.clone()),
+                });
+                message.mark_fuzzy();
+                Some(message)
+            }
+        };
+        // The header is taken as a whole, as both sides bump fields such as
+        // PO-Revision-Date that would otherwise always conflict.
+        for message in &ours.messages {
+            if message.obsolete || message.is_header() || options.locked.contains(&message.key()) {
+                merged.messages.push(message.clone());
+            } else if let Some(message) = resolve(find(&base_keys, message), Some(message), find(&theirs_keys, message)) {
+                merged.messages.push(message);
+            }
+        }
+        for message in theirs.messages.iter().filter(|m| !m.obsolete && find(&ours_keys, m).is_none()) {
+            if message.is_header() {
+                merged.messages.insert(0, message.clone());
+            } else if let Some(message) = resolve(find(&base_keys, message), None, Some(message)) {
+                merged.messages.push(message);
+            }
+        }
+        merged.obsolete_lines = ours.obsolete_lines.clone();
+        merged.trailing_comments = ours.trailing_comments.clone();
+        (merged, conflicts)
+    }
+
+    /// Builds a catalog holding the header plus every entry that is new or
+    /// changed relative to `baseline`, for handing off to translators.
+    ///
+    /// An entry whose msgid was edited is matched to its baseline entry by
+    /// normalized whitespace, or else by the same first `#:` reference, and
+    /// gets `#| msgid` and `#| msgid_plural` comments with the old source.
+    pub fn diff_catalog(&self, baseline: &Pot) -> Pot {
+        let mut pot = Pot::new();
+        if let Some(header) = self.header() {
+            pot.messages.push(header.clone());
+        }
+        for message in &self.messages {
+            if message.is_header() || message.obsolete {
+                continue;
+            }
+            let context = message.context.as_deref();
+            let id = message.id.as_deref().unwrap_or_default();
+            let old = baseline.find_normalized(context, id).or_else(|| {
+                let reference = message.primary_reference()?;
+                baseline.entries().find(|old| {
+                    old.context == message.context
+                        && old.primary_reference() == Some(reference)
+                        && self.find(context, old.id.as_deref().unwrap_or_default()).is_none()
+                })
+            });
+            match old {
+                None => pot.messages.push(message.clone()),
+                Some(old) if old.id != message.id || old.id_plural != message.id_plural || old.strings != message.strings => {
+                    let mut message = message.clone();
+                    for (keyword, old, new) in [("msgid", &old.id, &message.id), ("msgid_plural", &old.id_plural, &message.id_plural)] {
+                        let old = match old {
+                            Some(old) if Some(old) != new.as_ref() => old,
+                            _ => continue,
+                        };
+                        // Keep a previous comment the entry already has, e.g.
+                        // from msgmerge.
+                        let prefix = format!("{} ", keyword);
+                        if message.comments.iter().any(|c| c.kind == PotCommentKind::Previous && c.content.starts_with(&prefix)) {
+                            continue;
+                        }
+                        let comment = PotComment{
+                            kind: PotCommentKind::Previous,
+                            content: format!("{} \"{}\"", keyword, EscapeStyle::Po.escape(old)),
+                            fields_before: 0,
+                        };
+                        message.comments.push(comment);
+                    }
+                    pot.messages.push(message);
+                },
+                Some(_) => (),
+            }
+        }
+        pot
+    }
+
+    /// Reads a catalog, skipping lines it can't make sense of.
+    ///
+    /// Quoted strings accept the C escapes `\a`, `\b`, `\f`, `\n`, `\r`,
+    /// `\t`, `\v`, `\\`, `\"`, `\'` and `\?`. Octal `\ooo` (one to three
+    /// digits) and hex `\xhh` (one or two digits) escapes give bytes, and a
+    /// run of them is decoded as UTF-8, so `\303\251` reads as `é`; bytes
+    /// that aren't valid UTF-8 are taken as Latin-1. `\uXXXX` takes exactly
+    /// four hex digits and `\UXXXXXXXX` exactly eight, and a `\u` surrogate
+    /// pair such as `\uD83D\uDE00` makes a single character. Any
+    /// other escape, or one that doesn't decode, is kept as written.
+    pub fn read<R: Read>(reader: &mut R) -> Pot {
+        Pot::read_with_options(reader, &ParserOptions::default())
+    }
+
+    pub fn read_with_options<R: Read>(reader: &mut R, options: &ParserOptions) -> Pot {
+        Pot::read_with(reader, options, |_| ())
+    }
+
+    /// Like `read_with_options`, but passes each warning about a malformed
+    /// line to `on_warning` as soon as it is found, e.g. to log it while a
+    /// large file is still being read.
+    pub fn read_with<R: Read, F: FnMut(ParseWarning)>(reader: &mut R, options: &ParserOptions, mut on_warning: F) -> Pot {
+        Pot::read_reporting(reader, options, &mut on_warning)
+    }
+
+    /// Like `read_with_options`, but also returns warnings about malformed
+    /// lines that were repaired or skipped.
+    pub fn read_with_warnings<R: Read>(reader: &mut R, options: &ParserOptions) -> (Pot, Vec<ParseWarning>) {
+        let mut warnings = Vec::new();
+        let pot = Pot::read_with(reader, options, |warning| warnings.push(warning));
+        (pot, warnings)
+    }
+
+    /// Reads a catalog, returning the location and bytes of every sequence that
+    /// wasn't valid UTF-8 and had to be replaced, for tracking down corrupt
+    /// strings in imported files.
+    pub fn read_diagnostic<R: Read>(reader: &mut R, options: &ParserOptions) -> (Pot, Vec<ParseWarning>) {
+        let (pot, mut warnings) = Pot::read_with_warnings(reader, options);
+        warnings.retain(|w| matches!(w.kind, ParseWarningKind::InvalidUtf8 { .. }));
+        (pot, warnings)
+    }
+
+    /// Like `read_with_options`, but strict: fails with the first keyword
+    /// that isn't part of the PO format, such as a misspelt `msgsrt`, instead
+    /// of skipping it.
+    pub fn try_read_with_options<R: Read>(reader: &mut R, options: &ParserOptions) -> Result<Pot, ParseWarning> {
+        let mut error = None;
+        let pot = Pot::read_with(reader, options, |warning| {
+            if error.is_none() && matches!(warning.kind, ParseWarningKind::UnknownKeyword(_)) {
+                error = Some(warning);
+            }
+        });
+        match error {
+            Some(error) => Err(error),
+            None => Ok(pot),
+        }
+    }
+
+    fn read_reporting<R: Read>(reader: &mut R, options: &ParserOptions, on_warning: &mut dyn FnMut(ParseWarning)) -> Pot {
+        let mut f = BufReader::new(reader);
+        let mut parser = Parser::new(options);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if f.read_until(b'\n', &mut buf).unwrap() == 0 {
+                break;
+            }
+            let mut start = 0;
+            while let Err(error) = std::str::from_utf8(&buf[start..]) {
+                let bad = start + error.valid_up_to();
+                let end = bad + error.error_len().unwrap_or(buf.len() - bad);
+                on_warning(ParseWarning{
+                    line: parser.line_number + 1,
+                    kind: ParseWarningKind::InvalidUtf8 { offset: parser.offset + bad, bytes: buf[bad..end].to_vec() },
+                });
+                start = end;
+            }
+            if let Some(warning) = parser.feed(&String::from_utf8_lossy(&buf), buf.len()) {
+                on_warning(warning);
+            }
+        }
+        parser.finish()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.write_with_options(writer, &WriteOptions::default())
+    }
+
+    pub fn write_with_options<W: Write>(&self, writer: &mut W, options: &WriteOptions) -> std::io::Result<()> {
+        let messages: Vec<&PotMessage> = if options.faithful {
+            self.messages.iter().collect()
+        } else {
+            self.messages.iter().filter(|m| !m.obsolete).chain(self.messages.iter().filter(|m| m.obsolete)).collect()
+        };
+        let newline = match options.line_ending {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        };
+        let nplurals = self.nplurals().filter(|&n| n > 0).unwrap_or(2);
+        let mut buf = String::new();
+        let mut crlf = String::new();
+        for (i, message) in messages.iter().enumerate() {
+            buf.clear();
+            match message.original {
+                Some(ref original) if message.can_reuse(original, options) => {
+                    buf.push_str(&original.text);
+                    if !buf.ends_with('\n') {
+                        buf.push_str(newline);
+                    }
+                },
+                _ => {
+                    write!(buf, "{}", FormattedMessage{ message, options, nplurals })
+                        .map_err(|_| std::io::Error::other("failed to format message"))?;
+                    // Values never contain a raw newline once escaped, so every
+                    // one left in the buffer ends a line. Both buffers are
+                    // reused, swapping places once converted.
+                    if options.line_ending == LineEnding::Crlf {
+                        crlf.clear();
+                        for line in buf.split_inclusive('\n') {
+                            crlf.push_str(line.strip_suffix('\n').unwrap_or(line));
+                            if line.ends_with('\n') {
+                                crlf.push_str("\r\n");
+                            }
+                        }
+                        std::mem::swap(&mut buf, &mut crlf);
+                    }
+                },
+            }
+            if i < messages.len() - 1 {
+                buf.push_str(newline);
+            }
+            writer.write_all(buf.as_bytes())?;
+        }
+        if !self.obsolete_lines.is_empty() {
+            if !self.messages.is_empty() {
+                writer.write_all(newline.as_ref())?;
+            }
+            for line in &self.obsolete_lines {
+                writer.write_all(format!("{}{}", line, newline).as_ref())?;
+            }
+        }
+        for line in &self.trailing_comments {
+            writer.write_all(format!("{}{}", line, newline).as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the translations out as Rust source defining
+    /// `pub fn lookup(key: &str) -> Option<&'static str>`, a `match` over the
+    /// keys returned by `pairs`, for embedding a catalog at compile time.
+    pub fn write_rust<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut pairs = BTreeMap::new();
+        for (key, value) in self.pairs() {
+            pairs.entry(key).or_insert(value);
+        }
+        let mut buf = String::new();
+        buf.push_str("// Generated from a PO catalog. Do not edit.\n\n");
+        buf.push_str("pub fn lookup(key: &str) -> Option<&'static str> {\n");
+        buf.push_str("    match key {\n");
+        for (key, value) in pairs {
+            // Debug formatting of a str is a valid Rust string literal.
+            writeln!(buf, "        {:?} => Some({:?}),", key, value).unwrap();
+        }
+        buf.push_str("        _ => None,\n");
+        buf.push_str("    }\n");
+        buf.push_str("}\n");
+        writer.write_all(buf.as_bytes())
+    }
+
+    /// Writes the catalog out, reads it back and checks nothing was lost or
+    /// changed along the way. A plural entry without forms is written with
+    /// empty ones, so it counts as unchanged when read back with those. The
+    /// catalog is written faithfully, so obsolete entries stay in place.
+    pub fn is_roundtrip_stable(&self) -> bool {
+        let mut buf = Vec::new();
+        if self.write_with_options(&mut buf, &WriteOptions { faithful: true, ..Default::default() }).is_err() {
+            return false;
+        }
+        // Raw obsolete lines only read back as such when collapsed again.
+        let options = ParserOptions { collapse_obsolete: !self.obsolete_lines.is_empty(), ..Default::default() };
+        let read = Pot::read_with_options(&mut buf.as_slice(), &options);
+        let untranslated_plural = |m: &PotMessage| m.id_plural.is_some() && m.strings.iter().all(String::is_empty);
+        let without_forms = |m: &PotMessage| PotMessage { strings: Vec::new(), ..m.clone() };
+        read.obsolete_lines == self.obsolete_lines
+            && read.trailing_comments == self.trailing_comments
+            && read.messages.len() == self.messages.len()
+            && read.messages.iter().zip(&self.messages).all(|(a, b)| {
+                a == b || (untranslated_plural(a) && untranslated_plural(b) && without_forms(a) == without_forms(b))
+            })
     }
 }
 
-impl Default for PotCommand {
-    fn default() -> Self {
-        PotCommand {
-            key: String::new(),
-            value: String::new(),
-            index: None
+impl<'a> Parser<'a> {
+    pub fn new(options: &'a ParserOptions) -> Self {
+        Parser {
+            options,
+            pot: Pot::new(),
+            message: PotMessage::new(),
+            command: PotCommand::new(),
+            line_number: 0,
+            offset: 0,
+            syncing: false,
+            plural_pending: false,
+            keyword: PotCommand::regex(),
+            continuation: Regex::new(r#"^"((?:[^"\\]|\\.)*)"$"#).unwrap(),
+            prefixed: String::new(),
+            stripped: String::new(),
+            skipped: String::new(),
+            leading: String::new(),
+            pending: Vec::new(),
+            pending_span: None,
+            pending_text: String::new(),
+            pending_gap: 0,
+            blank_lines: 0,
+        }
+    }
+
+    /// Drops the entry being parsed and skips lines until the next comment,
+    /// `msgctxt` or `msgid`, where a new entry can cleanly start. Call this
+    /// before feeding lines taken from the middle of a catalog.
+    pub fn resync(&mut self) {
+        self.message = PotMessage::new();
+        self.command = PotCommand::new();
+        self.plural_pending = false;
+        self.syncing = true;
+        self.pending.clear();
+        self.pending_span = None;
+        self.pending_text.clear();
+        self.blank_lines = 0;
+    }
+
+    fn is_entry_boundary(s: &str) -> bool {
+        let s = match s.strip_prefix("#~") {
+            Some(rest) if rest.starts_with('|') => return true,
+            Some(rest) => rest.trim_start_matches([' ', '\t']),
+            None => s,
+        };
+        PotComment::is_comment(s) || s.starts_with("msgctxt") || (s.starts_with("msgid") && !s.starts_with("msgid_plural"))
+    }
+
+    /// Parses one line, with or without its line ending. Returns a warning if
+    /// the line had to be repaired or skipped. Line numbers and spans count
+    /// from the first line fed to this parser.
+    pub fn feed_line(&mut self, line: &str) -> Option<ParseWarning> {
+        self.feed(line, line.len())
+    }
+
+    /// Parses one line that took up `len` bytes of input, which can differ
+    /// from `line.len()` when invalid UTF-8 was replaced.
+    fn feed(&mut self, line: &str, len: usize) -> Option<ParseWarning> {
+        let warning = self.parse_line(line, len);
+        if !self.options.keep_original {
+            return warning;
+        }
+        // The line belongs to the current message if it ended its span, and
+        // so do the lines skipped since the message's previous line. Lines of
+        // pending comments are already in `pending_text`.
+        if self.message.span.as_ref().map(|s| s.end) == Some(self.offset) {
+            match self.message.original {
+                Some(ref mut original) => {
+                    original.text.push_str(&self.skipped);
+                    original.text.push_str(line);
+                    self.skipped.clear();
+                },
+                None => {
+                    self.skipped.clear();
+                    self.message.original = Some(OriginalText { text: line.to_string(), fingerprint: 0 });
+                },
+            }
+        } else if self.pending_span.as_ref().map(|s| s.end) != Some(self.offset) {
+            self.skipped.push_str(line);
+        }
+        warning
+    }
+
+    fn parse_line(&mut self, line: &str, len: usize) -> Option<ParseWarning> {
+        let options = self.options;
+        let span = self.offset..self.offset + len;
+        self.offset += len;
+        self.line_number += 1;
+        // Editors sometimes indent lines; gettext wants column 0 but we accept either.
+        let mut s = line.trim_end_matches(['\n', '\r']).trim_start_matches([' ', '\t']);
+        if options.comment_prefix != "#" && !options.comment_prefix.is_empty() && s.starts_with(&options.comment_prefix) {
+            self.prefixed.clear();
+            self.prefixed.push('#');
+            self.prefixed.push_str(&s[options.comment_prefix.len()..]);
+            s = &self.prefixed;
+        }
+        if s.is_empty() {
+            if self.pending.is_empty() {
+                self.blank_lines += 1;
+            } else {
+                self.pending_text.push_str(line);
+                self.pending_span = self.pending_span.take().map(|pending| pending.start..span.end);
+            }
+            return None;
+        }
+        let blank_lines = std::mem::take(&mut self.blank_lines);
+        if self.syncing {
+            if !Parser::is_entry_boundary(s) {
+                return None;
+            }
+            self.syncing = false;
+        }
+        let obsolete = s.starts_with("#~");
+        if obsolete && options.collapse_obsolete {
+            // The comments before the block belong to it, and are kept as
+            // written along with its lines.
+            self.pot.obsolete_lines.extend(self.pending_text.lines().map(str::to_string));
+            self.pending.clear();
+            self.pending_span = None;
+            self.pending_text.clear();
+            self.leading.clear();
+            self.pot.obsolete_lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+            return None;
+        }
+        if obsolete {
+            if s[2..].starts_with('|') {
+                self.stripped.clear();
+                self.stripped.push('#');
+                self.stripped.push_str(&s[2..]);
+                s = &self.stripped;
+            } else {
+                s = s[2..].trim_start_matches([' ', '\t']);
+            }
+        }
+        let message = &mut self.message;
+        if let Ok(mut comment) = s.parse::<PotComment>() {
+            self.plural_pending = false;
+            self.command = PotCommand::new();
+            if message.is_valid() || message.is_empty() {
+                if self.pending.is_empty() {
+                    self.pending_gap = blank_lines;
+                    self.leading = std::mem::take(&mut self.skipped);
+                } else {
+                    self.pending_text.push_str(&self.skipped);
+                    self.skipped.clear();
+                }
+                self.pending_text.push_str(line);
+                self.pending.push(comment);
+                self.pending_span = Some(match self.pending_span.take() {
+                    Some(existing) => existing.start..span.end,
+                    None => span,
+                });
+            } else {
+                comment.fields_before = message.field_count();
+                message.comments.push(comment);
+                message.extend_span(span);
+            }
+        } else if let Ok(mut cmd) = PotCommand::parse(&self.keyword, s) {
+            if let Some(key) = options.keyword_aliases.get(&cmd.key) {
+                cmd.key = key.clone();
+            }
+            if !cmd.is_known() {
+                self.command = PotCommand::new();
+                return Some(ParseWarning{ line: self.line_number, kind: ParseWarningKind::UnknownKeyword(cmd.key) });
+            }
+            if let Some(index) = cmd.index.filter(|&index| index >= MAX_PLURAL_FORMS) {
+                self.command = PotCommand::new();
+                return Some(ParseWarning{ line: self.line_number, kind: ParseWarningKind::PluralIndexOutOfRange(index) });
+            }
+            if !self.pending.is_empty() {
+                let continues = !message.is_empty() && message.obsolete == obsolete && cmd.can_apply(message);
+                self.settle_pending(continues);
+            }
+            let message = &mut self.message;
+            if cmd.is_repeated_msgstr(message) && message.obsolete == obsolete {
+                cmd.force_apply(message);
+                message.extend_span(span);
+                self.command = cmd;
+                return Some(ParseWarning{ line: self.line_number, kind: ParseWarningKind::DuplicateMsgstr });
+            }
+            if cmd.key == "msgid_plural" && self.plural_pending && message.obsolete == obsolete {
+                self.plural_pending = false;
+                cmd.force_apply(message);
+                message.extend_span(span);
+                self.command = cmd;
+                return Some(ParseWarning{ line: self.line_number, kind: ParseWarningKind::MsgidPluralAfterMsgstr });
+            }
+            if message.id.is_some() && (!cmd.can_apply(message) || message.obsolete != obsolete) {
+                self.pot.messages.push(std::mem::take(message).sealed());
+            }
+            message.obsolete = obsolete;
+            message.extend_span(span);
+            if cmd.apply(message) {
+                // Remember indexed msgstrs on a singular message so a late
+                // msgid_plural can still be attached to it.
+                self.plural_pending = cmd.key == "msgstr" && cmd.index.is_some() && message.id_plural.is_none();
+                self.command = cmd;
+            } else {
+                self.command = PotCommand::new();
+                return Some(ParseWarning{ line: self.line_number, kind: ParseWarningKind::MisplacedKeyword(cmd.key) });
+            }
+        } else if let Some(caps) = self.continuation.captures(s) {
+            let s_msg = caps.get(1).map(|m| m.as_str()).unwrap_or_default();
+            self.command.append(message, &unescape_string(s_msg));
+            if self.pending.is_empty() {
+                message.extend_span(span);
+            }
+        }
+        None
+    }
+
+    /// Moves the pending comments onto the current message if `continues`,
+    /// after its keywords so far. Otherwise the current message is complete
+    /// and they start the next one.
+    fn settle_pending(&mut self, continues: bool) {
+        let comments = std::mem::take(&mut self.pending);
+        let span = self.pending_span.take();
+        if continues {
+            let fields = self.message.field_count();
+            self.message.comments.extend(comments.into_iter().map(|comment| PotComment { fields_before: fields, ..comment }));
+            if let Some(span) = span {
+                self.message.extend_span(span);
+            }
+            if let Some(original) = self.message.original.as_mut() {
+                original.text.push_str(&self.leading);
+                original.text.push_str(&self.pending_text);
+            }
+        } else {
+            if !self.message.is_empty() {
+                self.pot.messages.push(std::mem::take(&mut self.message).sealed());
+            }
+            self.message.comments = comments;
+            self.message.span = span;
+            if self.options.keep_original {
+                self.message.original = Some(OriginalText { text: self.pending_text.clone(), fingerprint: 0 });
+            }
+        }
+        self.leading.clear();
+        self.pending_text.clear();
+    }
+
+    /// Flushes the last entry and returns the parsed catalog.
+    pub fn finish(mut self) -> Pot {
+        if !self.message.is_empty() {
+            self.pot.messages.push(self.message.sealed());
+        }
+        // Comments with no entry after them close the file rather than
+        // annotate the last entry.
+        if !self.pending.is_empty() {
+            self.pot.trailing_comments = std::iter::repeat_n(String::new(), self.pending_gap)
+                .chain(self.pending_text.lines().map(str::to_string))
+                .collect();
         }
+        self.pot
+    }
+}
+
+impl FromStr for PotCommand {
+    type Err = ();
+    fn from_str(s: &str) -> Result<PotCommand, Self::Err> {
+        PotCommand::parse(&PotCommand::regex(), s)
     }
 }
 
 impl PotCommand {
+    fn regex() -> Regex {
+        Regex::new(r#"^(?P<cmd>[a-z_]+)(?:\[(?P<idx>[0-9]+)\])? "(?P<val>(?:[^"\\]|\\.)*)""#).unwrap()
+    }
+
+    /// Parses a keyword line with a regex from `PotCommand::regex`, so
+    /// callers parsing many lines only compile it once.
+    fn parse(re: &Regex, s: &str) -> Result<PotCommand, ()> {
+        if let Some(caps) = re.captures(s) {
+            let cmd = caps.name("cmd").map(|m| m.as_str()).unwrap_or_default();
+            let idx = caps.name("idx").map(|m| m.as_str()).unwrap_or_default();
+            let val = caps.name("val").map(|m| m.as_str()).unwrap_or_default();
+
+            let mut cmd = PotCommand{
+                key: cmd.to_string(),
+                index: None,
+                value: unescape_string(val),
+            };
+
+            if !idx.is_empty() {
+                cmd.index = Some(idx.parse::<usize>().unwrap_or(usize::MAX));
+            }
+
+            return Ok(cmd);
+        }
+        Err(())
+    }
+
     pub fn new() -> Self {
         Default::default()
     }
 
+    fn is_known(&self) -> bool {
+        matches!(self.key.as_str(), "msgctxt" | "msgid" | "msgid_plural" | "msgstr")
+    }
+
+    /// Whether this is a second bare `msgstr` for a singular message whose
+    /// first `msgstr` was empty, which is collapsed rather than starting a new
+    /// message.
+    fn is_repeated_msgstr(&self, msg: &PotMessage) -> bool {
+        self.key == "msgstr" && self.index.is_none() && msg.id_plural.is_none()
+            && msg.strings.len() == 1 && msg.strings[0].is_empty()
+    }
+
     fn can_apply(&self, msg: &PotMessage) -> bool {
         match self.key.as_str() {
             "msgctxt" => msg.context.is_none() && msg.id.is_none() && msg.id_plural.is_none() && msg.strings.is_empty(),
             "msgid" => msg.id.is_none() && msg.id_plural.is_none() && msg.strings.is_empty(),
-            "msgid_plural" => msg.id_plural.is_none() && msg.strings.is_empty(),
+            "msgid_plural" => msg.id.is_some() && msg.id_plural.is_none() && msg.strings.is_empty(),
             "msgstr" => {
                 let idx = self.index.unwrap_or_default();
-                return idx + 1 > msg.strings.len();
+                msg.id.is_some() && (idx >= msg.strings.len() || (self.index.is_some() && msg.strings[idx].is_empty()))
             },
             _ => false,
         }
@@ -278,22 +2442,43 @@ impl PotCommand {
             "msgid" => { msg.id = Some(val) },
             "msgid_plural" => { msg.id_plural = Some(val) },
             "msgstr" => {
+                // Forms are stored by index, so msgstr[1] before msgstr[0]
+                // still lands in the right place.
                 let idx = self.index.unwrap_or_default();
-                if idx + 1 > msg.strings.len() {
-                    msg.strings.push(val);
-                } else {
-                    msg.strings[idx] = val;
+                if idx >= msg.strings.len() {
+                    msg.strings.resize(idx + 1, String::new());
                 }
+                msg.strings[idx] = val;
             },
             _ => (),
         }
     }
 
     fn apply(&self, msg: &mut PotMessage) -> bool {
-        if !self.can_apply(&msg) {
+        if !self.can_apply(msg) {
             return false;
         }
         self.force_apply(msg);
         true
     }
+
+    /// Appends a continuation line to the field this command set. Appending
+    /// in place rather than re-applying the whole value keeps long wrapped
+    /// strings linear to read.
+    fn append(&self, msg: &mut PotMessage, s: &str) {
+        let field = match self.key.as_str() {
+            "msgctxt" => msg.context.get_or_insert_with(String::new),
+            "msgid" => msg.id.get_or_insert_with(String::new),
+            "msgid_plural" => msg.id_plural.get_or_insert_with(String::new),
+            "msgstr" => {
+                let idx = self.index.unwrap_or_default();
+                if idx >= msg.strings.len() {
+                    msg.strings.resize(idx + 1, String::new());
+                }
+                &mut msg.strings[idx]
+            },
+            _ => return,
+        };
+        field.push_str(s);
+    }
 }
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use crate::Pot;
+
+/// A match returned by `TranslationMemory::suggest`. `score` runs from 0.0
+/// to 1.0, where 1.0 is an exact match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion<'a> {
+    pub source: &'a str,
+    pub translation: &'a str,
+    pub score: f64,
+}
+
+/// Source strings mapped to their translations, built by `Pot::to_memory`
+/// for pre-filling other catalogs.
+#[derive(Default)]
+pub struct TranslationMemory {
+    entries: BTreeMap<String, String>,
+}
+
+/// Similarity of two strings from 0.0 to 1.0, based on the character-level
+/// edit distance relative to the longer string.
+fn similarity(a: &[char], b: &[char]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + (ca != cb) as usize;
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+impl TranslationMemory {
+    pub(crate) fn new(pot: &Pot) -> TranslationMemory {
+        let mut memory = TranslationMemory::default();
+        for message in pot.entries().filter(|m| m.is_translated() && !m.is_fuzzy()) {
+            let sources = message.id.iter().chain(message.id_plural.iter());
+            for (source, translation) in sources.zip(&message.strings) {
+                memory.insert(source, translation);
+            }
+        }
+        memory
+    }
+
+    /// Adds a pair, keeping the existing translation if `source` is known.
+    pub fn insert(&mut self, source: &str, translation: &str) {
+        self.entries.entry(source.to_string()).or_insert_with(|| translation.to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entry whose source is most similar to `source`, or `None`
+    /// when the memory is empty.
+    pub fn suggest(&self, source: &str) -> Option<Suggestion<'_>> {
+        if let Some((source, translation)) = self.entries.get_key_value(source) {
+            return Some(Suggestion { source, translation, score: 1.0 });
+        }
+        let wanted: Vec<char> = source.chars().collect();
+        let mut best: Option<Suggestion<'_>> = None;
+        for (candidate, translation) in &self.entries {
+            let length = candidate.chars().count();
+            // The length difference alone bounds the score from above.
+            let bound = 1.0 - wanted.len().abs_diff(length) as f64 / wanted.len().max(length).max(1) as f64;
+            if best.as_ref().map(|b| bound <= b.score).unwrap_or_default() {
+                continue;
+            }
+            let score = similarity(&wanted, &candidate.chars().collect::<Vec<_>>());
+            if best.as_ref().map(|b| score > b.score).unwrap_or(true) {
+                best = Some(Suggestion { source: candidate, translation, score });
+            }
+        }
+        best
+    }
+}
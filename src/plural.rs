@@ -0,0 +1,231 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+pub type Selector = dyn Fn(u64) -> usize + Send + Sync;
+
+/// Error returned when a `Plural-Forms` expression can't be compiled.
+#[derive(Debug, PartialEq)]
+pub enum PluralParseError {
+    /// The header has no `plural=` expression.
+    Missing,
+    /// The expression is malformed at the given byte offset.
+    Syntax(usize),
+}
+
+impl fmt::Display for PluralParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PluralParseError::Missing => write!(f, "missing plural expression"),
+            PluralParseError::Syntax(offset) => write!(f, "invalid plural expression at offset {}", offset),
+        }
+    }
+}
+
+enum Expr {
+    N,
+    Number(u64),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Expr {
+    fn eval(&self, n: u64) -> u64 {
+        match self {
+            Expr::N => n,
+            Expr::Number(value) => *value,
+            Expr::Not(expr) => (expr.eval(n) == 0) as u64,
+            Expr::Conditional(cond, then, otherwise) => {
+                if cond.eval(n) != 0 { then.eval(n) } else { otherwise.eval(n) }
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(n);
+                match op {
+                    Op::Or => (lhs != 0 || rhs.eval(n) != 0) as u64,
+                    Op::And => (lhs != 0 && rhs.eval(n) != 0) as u64,
+                    Op::Eq => (lhs == rhs.eval(n)) as u64,
+                    Op::Ne => (lhs != rhs.eval(n)) as u64,
+                    Op::Lt => (lhs < rhs.eval(n)) as u64,
+                    Op::Le => (lhs <= rhs.eval(n)) as u64,
+                    Op::Gt => (lhs > rhs.eval(n)) as u64,
+                    Op::Ge => (lhs >= rhs.eval(n)) as u64,
+                    Op::Add => lhs.wrapping_add(rhs.eval(n)),
+                    Op::Sub => lhs.wrapping_sub(rhs.eval(n)),
+                    Op::Mul => lhs.wrapping_mul(rhs.eval(n)),
+                    Op::Div => lhs.checked_div(rhs.eval(n)).unwrap_or_default(),
+                    Op::Rem => lhs.checked_rem(rhs.eval(n)).unwrap_or_default(),
+                }
+            },
+        }
+    }
+}
+
+/// How deeply parentheses, `!`, `?:` and operators may nest. Real rules
+/// stay far below this, and the header comes from untrusted files, so a
+/// deeper expression is rejected rather than risk overflowing the stack.
+const MAX_DEPTH: usize = 100;
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.input[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), PluralParseError> {
+        if self.eat(token) { Ok(()) } else { Err(PluralParseError::Syntax(self.pos)) }
+    }
+
+    /// Goes one level deeper. Callers restore `depth` once the nested part
+    /// parsed; after an error the parser is dropped anyway.
+    fn descend(&mut self) -> Result<(), PluralParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(PluralParseError::Syntax(self.pos));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn conditional(&mut self) -> Result<Expr, PluralParseError> {
+        let cond = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(cond);
+        }
+        let depth = self.depth;
+        self.descend()?;
+        let then = self.conditional()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        self.depth = depth;
+        Ok(Expr::Conditional(Box::new(cond), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, PluralParseError> {
+        const LEVELS: &[&[(&str, Op)]] = &[
+            &[("||", Op::Or)],
+            &[("&&", Op::And)],
+            &[("==", Op::Eq), ("!=", Op::Ne)],
+            &[("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)],
+            &[("+", Op::Add), ("-", Op::Sub)],
+            &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let depth = self.depth;
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for (token, op) in LEVELS[level] {
+                if self.eat(token) {
+                    // Chained operators nest too, as `a + b + c` evaluates
+                    // `(a + b)` first.
+                    self.descend()?;
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            self.depth = depth;
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, PluralParseError> {
+        let depth = self.depth;
+        if self.eat("!") {
+            self.descend()?;
+            let expr = self.unary()?;
+            self.depth = depth;
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+        if self.eat("(") {
+            self.descend()?;
+            let expr = self.conditional()?;
+            self.expect(")")?;
+            self.depth = depth;
+            return Ok(expr);
+        }
+        if self.eat("n") {
+            return Ok(Expr::N);
+        }
+        let digits = self.input[self.pos..].len() - self.input[self.pos..].trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return Err(PluralParseError::Syntax(self.pos));
+        }
+        let value = self.input[self.pos..self.pos + digits].parse().map_err(|_| PluralParseError::Syntax(self.pos))?;
+        self.pos += digits;
+        Ok(Expr::Number(value))
+    }
+}
+
+/// Remembers the last expression compiled for a catalog, so lookups only
+/// parse it again once the header changes.
+#[derive(Default)]
+pub struct Cache(Mutex<Option<(String, Option<Arc<Selector>>)>>);
+
+// A cache never makes two catalogs different.
+impl PartialEq for Cache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Cache {
+    /// Returns `expression` compiled, or `None` if it doesn't compile.
+    pub fn get(&self, expression: &str) -> Option<Arc<Selector>> {
+        let mut cached = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match *cached {
+            Some((ref source, ref selector)) if source == expression => selector.clone(),
+            _ => {
+                let selector: Option<Arc<Selector>> = compile(expression).ok().map(Arc::from);
+                *cached = Some((expression.to_string(), selector.clone()));
+                selector
+            },
+        }
+    }
+}
+
+/// Compiles a C plural expression such as `(n != 1)` into a function from a
+/// count to a plural form index.
+pub fn compile(expression: &str) -> Result<Box<Selector>, PluralParseError> {
+    let mut parser = Parser { input: expression, pos: 0, depth: 0 };
+    let expr = parser.conditional()?;
+    parser.eat(";");
+    parser.skip_whitespace();
+    if parser.pos != expression.len() {
+        return Err(PluralParseError::Syntax(parser.pos));
+    }
+    Ok(Box::new(move |n| expr.eval(n) as usize))
+}
@@ -0,0 +1,188 @@
+use potty::{EscapeStyle, MergeOptions, Pot, PotCommentKind, SortKey, SortOptions, Suggestion, TranslationMemory};
+
+fn read(s: &str) -> Pot {
+    Pot::read(&mut s.as_bytes())
+}
+
+#[test]
+fn diff_catalog_records_previous_msgid() {
+    let baseline = read("#: src/main.rs:10\nmsgid \"Open file\"\nmsgstr \"Datei öffnen\"\n");
+    let current = read("#: src/main.rs:10\nmsgid \"Open a file\"\nmsgstr \"\"\n");
+    let diff = current.diff_catalog(&baseline);
+    let message = diff.find(None, "Open a file").unwrap();
+    let previous: Vec<&str> = message.comments.iter()
+        .filter(|c| c.kind == PotCommentKind::Previous)
+        .map(|c| c.content.as_str())
+        .collect();
+    assert_eq!(previous, ["msgid \"Open file\""]);
+
+    // A second diff doesn't stack another comment on the first.
+    let again = diff.diff_catalog(&baseline);
+    let message = again.find(None, "Open a file").unwrap();
+    assert_eq!(message.comments.iter().filter(|c| c.kind == PotCommentKind::Previous).count(), 1);
+}
+
+#[test]
+fn entries_rev_starts_with_the_obsolete_tail() {
+    let pot = read("msgid \"\"\nmsgstr \"\"\n\nmsgid \"a\"\nmsgstr \"A\"\n\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n");
+    let ids: Vec<&str> = pot.entries_rev().map(|m| m.id.as_deref().unwrap()).collect();
+    assert_eq!(ids, ["old", "a"]);
+}
+
+#[test]
+fn template_detection() {
+    let template = read("#, fuzzy\nmsgid \"\"\nmsgstr \"Language: \\n\"\n\nmsgid \"a\"\nmsgstr \"\"\n");
+    assert!(template.header().unwrap().is_fuzzy());
+    assert!(template.is_template());
+
+    let translated = read("msgid \"\"\nmsgstr \"Language: de\\n\"\n\nmsgid \"a\"\nmsgstr \"A\"\n\nmsgid \"b\"\nmsgstr \"\"\n");
+    assert!(!translated.header().unwrap().is_fuzzy());
+    assert!(!translated.is_template());
+}
+
+#[test]
+fn c_format_specifiers() {
+    let cases: &[(&str, &[&str])] = &[
+        ("100%% done", &[]),
+        ("%5.2f MB", &["%5.2f"]),
+        ("%2$s of %1$s", &["%2$s", "%1$s"]),
+        ("%ld items, %s", &["%ld", "%s"]),
+        ("%-10s|%%|%d", &["%-10s", "%d"]),
+    ];
+    for (s, expected) in cases {
+        assert_eq!(potty::count_c_format_specifiers(s), *expected, "{}", s);
+    }
+}
+
+#[test]
+fn obsolete_entries_stay_out_of_lookups() {
+    let pot = read("#: src/a.rs:1\nmsgid \"a\"\nmsgstr \"\"\n\n#: src/a.rs:2\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n");
+    assert_eq!(pot.gettext("old"), "old");
+    assert!(pot.find(None, "old").is_none());
+    assert!(pot.find_including_obsolete(None, "old").unwrap().obsolete);
+    assert_eq!(pot.entries().count(), 1);
+    assert_eq!(pot.entries_including_obsolete().count(), 2);
+
+    let stats = pot.stats();
+    assert_eq!((stats.total, stats.translated, stats.untranslated, stats.obsolete), (1, 0, 1, 1));
+    assert_eq!(pot.completeness(), 0.0);
+    assert_eq!(pot.coverage_by_file()["src/a.rs"], (0, 1));
+    assert_eq!(pot.reference_count(), 1);
+    assert_eq!(pot.referenced_files(), ["src/a.rs"]);
+}
+
+#[test]
+fn normalized_references_are_sorted_and_deduplicated() {
+    let pot = read("#: src/b.rs:20 src/a.rs:5\n#: src/b.rs:3 src/a.rs:5 src/b.rs:20 README\nmsgid \"a\"\nmsgstr \"\"\n");
+    let references = pot.messages[0].normalized_references();
+    let expected = [("README", None), ("src/a.rs", Some(5)), ("src/b.rs", Some(3)), ("src/b.rs", Some(20))];
+    assert_eq!(references, expected.map(|(path, line)| (path.to_string(), line)));
+}
+
+#[test]
+fn escaped_pairs_for_each_style() {
+    let pot = read("msgid \"a\"\nmsgstr \"\"\n\"Say \\\"hi\\\" & <wave>\\n\"\n\"it's\\tdone\"\n");
+    let escaped = |style| pot.escaped_pairs(style).next().unwrap().1;
+    assert_eq!(escaped(EscapeStyle::Json), "Say \\\"hi\\\" & <wave>\\nit's\\tdone");
+    assert_eq!(escaped(EscapeStyle::Xml), "Say &quot;hi&quot; &amp; &lt;wave&gt;\nit&apos;s\tdone");
+    assert_eq!(escaped(EscapeStyle::Po), "Say \\\"hi\\\" & <wave>\\nit's\\tdone");
+}
+
+#[test]
+fn sort_pins_header_and_obsolete_entries() {
+    let source = "#~ msgid \"z-old\"\n#~ msgstr \"\"\n\nmsgid \"c\"\nmsgstr \"\"\n\nmsgid \"\"\nmsgstr \"Language: de\\n\"\n\n\
+        #~ msgid \"a-old\"\n#~ msgstr \"\"\n\nmsgid \"b\"\nmsgstr \"\"\n";
+    let ids = |pot: &Pot| pot.messages.iter().map(|m| m.id.clone().unwrap()).collect::<Vec<_>>();
+
+    let mut pot = read(source);
+    pot.sort_with(SortKey::Msgid, &SortOptions { obsolete_last: true });
+    assert_eq!(ids(&pot), ["", "b", "c", "a-old", "z-old"]);
+
+    let mut pot = read(source);
+    pot.sort(SortKey::Msgid);
+    assert_eq!(ids(&pot), ["", "a-old", "b", "c", "z-old"]);
+}
+
+#[test]
+fn locked_entries_survive_merge() {
+    let base = read("msgid \"a\"\nmsgstr \"Approved\"\n\nmsgid \"b\"\nmsgstr \"Old\"\n");
+    let ours = read("msgid \"a\"\nmsgstr \"Approved\"\n\nmsgid \"b\"\nmsgstr \"Ours\"\n");
+    let theirs = read("msgid \"a\"\nmsgstr \"Machine\"\n\nmsgid \"b\"\nmsgstr \"Theirs\"\n");
+
+    let (merged, conflicts) = Pot::merge3(&base, &ours, &theirs);
+    assert_eq!(merged.gettext("a"), "Machine");
+    assert_eq!(conflicts.len(), 1);
+
+    let options = MergeOptions { locked: ["a", "b"].iter().map(|k| k.to_string()).collect(), ..Default::default() };
+    let (merged, conflicts) = Pot::merge3_with(&base, &ours, &theirs, &options);
+    assert!(conflicts.is_empty());
+    assert_eq!(merged.gettext("a"), "Approved");
+    assert_eq!(merged.gettext("b"), "Ours");
+    assert!(merged.entries().all(|m| !m.is_fuzzy()));
+}
+
+#[test]
+fn obsolete_header_is_ignored() {
+    let mut pot = read("msgid \"a\"\nmsgstr \"A\"\n\n#~ msgid \"\"\n#~ msgstr \"Language: fr\\n\"\n");
+    assert!(pot.header().is_none());
+    assert_eq!(pot.header_field("Language"), None);
+
+    pot.set_language("de");
+    assert_eq!(pot.header_field("Language"), Some("de"));
+    assert!(!pot.messages[0].obsolete);
+    assert_eq!(pot.messages[2].strings, ["Language: fr\n"]);
+}
+
+#[test]
+fn chunks_keep_every_untranslated_entry() {
+    let pot = read("msgid \"\"\nmsgstr \"Language: de\\n\"\n\nmsgid \"one two three\"\nmsgstr \"\"\n\n\
+        msgid \"four\"\nmsgstr \"\"\n\nmsgid \"five six\"\nmsgstr \"\"\n\nmsgid \"done\"\nmsgstr \"Fertig\"\n");
+    let chunks = pot.chunks(2);
+    let ids = |pot: &Pot| pot.entries().map(|m| m.id.clone().unwrap()).collect::<Vec<_>>();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(ids(&chunks[0]), ["one two three"]);
+    assert_eq!(ids(&chunks[1]), ["four", "five six"]);
+    assert!(chunks.iter().all(|chunk| chunk.header_field("Language") == Some("de")));
+
+    let chunks = pot.chunks(0);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(ids(&chunks[0]), ["one two three", "four", "five six"]);
+}
+
+#[test]
+fn compiled_catalog_lookups() {
+    let catalog = read("msgid \"\"\nmsgstr \"Plural-Forms: nplurals=2; plural=(n > 1);\\n\"\n\n\
+        msgid \"Open\"\nmsgstr \"Ouvrir\"\n\n\
+        msgctxt \"menu\"\nmsgid \"Open\"\nmsgstr \"Ouvrir le menu\"\n\n\
+        msgid \"file\"\nmsgid_plural \"files\"\nmsgstr[0] \"fichier\"\nmsgstr[1] \"fichiers\"\n\n\
+        #, fuzzy\nmsgid \"Close\"\nmsgstr \"Fermer\"\n\n\
+        msgid \"Save\"\nmsgstr \"\"\n\n\
+        #~ msgid \"Quit\"\n#~ msgstr \"Quitter\"\n").compile();
+    assert_eq!(catalog.len(), 3);
+    assert_eq!(catalog.gettext("Open"), "Ouvrir");
+    assert_eq!(catalog.pgettext("menu", "Open"), "Ouvrir le menu");
+    assert_eq!(catalog.pgettext("toolbar", "Open"), "Open");
+    assert_eq!(catalog.ngettext("file", "files", 0), "fichier");
+    assert_eq!(catalog.ngettext("file", "files", 2), "fichiers");
+    assert_eq!(catalog.ngettext("dir", "dirs", 1), "dir");
+    assert_eq!(catalog.ngettext("dir", "dirs", 2), "dirs");
+    assert_eq!(catalog.gettext("Close"), "Close");
+    assert_eq!(catalog.gettext("Save"), "Save");
+    assert_eq!(catalog.gettext("Quit"), "Quit");
+    assert_eq!(catalog.gettext("Missing"), "Missing");
+}
+
+#[test]
+fn translation_memory_suggestions() {
+    assert_eq!(TranslationMemory::default().suggest("Open file"), None);
+
+    let memory = read("msgid \"Open file\"\nmsgstr \"Datei öffnen\"\n\n\
+        msgid \"Open folder\"\nmsgstr \"Ordner öffnen\"\n\n\
+        msgid \"Close file\"\nmsgstr \"Datei schließen\"\n\n\
+        msgid \"Open a very long list of recent files\"\nmsgstr \"Eine lange Liste öffnen\"\n\n\
+        #, fuzzy\nmsgid \"Open files\"\nmsgstr \"Dateien öffnen\"\n").to_memory();
+    assert_eq!(memory.len(), 4);
+    assert_eq!(memory.suggest("Close file"), Some(Suggestion { source: "Close file", translation: "Datei schließen", score: 1.0 }));
+    assert_eq!(memory.suggest("Open files"), Some(Suggestion { source: "Open file", translation: "Datei öffnen", score: 0.9 }));
+    assert_eq!(memory.suggest("Open folders").unwrap().source, "Open folder");
+}
@@ -0,0 +1,78 @@
+use potty::Pot;
+
+fn read(s: &str) -> Pot {
+    Pot::read(&mut s.as_bytes())
+}
+
+#[test]
+fn ngettext_follows_header_changes() {
+    let mut pot = read("msgid \"\"\nmsgstr \"Plural-Forms: nplurals=2; plural=(n != 1);\\n\"\n\n\
+        msgid \"file\"\nmsgid_plural \"files\"\nmsgstr[0] \"one\"\nmsgstr[1] \"other\"\n");
+    assert_eq!(pot.ngettext("file", "files", 0), "other");
+    pot.set_header_field("Plural-Forms", "nplurals=2; plural=(n > 1);");
+    assert_eq!(pot.ngettext("file", "files", 0), "one");
+}
+
+#[test]
+fn deeply_nested_rule_is_rejected() {
+    let expression = format!("nplurals=2; plural={}n{};", "(".repeat(200_000), ")".repeat(200_000));
+    let mut pot = read("msgid \"file\"\nmsgid_plural \"files\"\nmsgstr[0] \"one\"\nmsgstr[1] \"other\"\n");
+    pot.set_header_field("Plural-Forms", &expression);
+    assert!(matches!(pot.plural_selector(), Err(potty::PluralParseError::Syntax(_))));
+    assert_eq!(pot.ngettext("file", "files", 2), "other");
+
+    let chained = format!("nplurals=2; plural=n{};", "+n".repeat(200_000));
+    pot.set_header_field("Plural-Forms", &chained);
+    assert!(pot.plural_selector().is_err());
+}
+
+#[test]
+fn common_rules_compile() {
+    let mut pot = Pot::with_header("ar");
+    assert_eq!(pot.plural_selector().unwrap()(3), 3);
+    pot.set_language("ru");
+    let selector = pot.plural_selector().unwrap();
+    assert_eq!((selector(1), selector(2), selector(5), selector(21)), (0, 1, 2, 0));
+}
+
+#[test]
+fn fix_plural_forms_count_pads_and_truncates_safely() {
+    let mut pot = read("msgid \"\"\nmsgstr \"Plural-Forms: nplurals=3; plural=(n==1 ? 0 : n==2 ? 1 : 2);\\n\"\n\n\
+        msgid \"short\"\nmsgid_plural \"shorts\"\nmsgstr[0] \"a\"\nmsgstr[1] \"b\"\n\n\
+        msgid \"empty tail\"\nmsgid_plural \"empty tails\"\nmsgstr[0] \"a\"\nmsgstr[1] \"b\"\nmsgstr[2] \"c\"\nmsgstr[3] \"\"\nmsgstr[4] \"\"\n\n\
+        msgid \"translated tail\"\nmsgid_plural \"translated tails\"\nmsgstr[0] \"a\"\nmsgstr[1] \"b\"\nmsgstr[2] \"\"\nmsgstr[3] \"d\"\n\n\
+        msgid \"exact\"\nmsgid_plural \"exacts\"\nmsgstr[0] \"a\"\nmsgstr[1] \"b\"\nmsgstr[2] \"c\"\n");
+    assert_eq!(pot.plural_consistency(), [1, 2, 3]);
+    assert_eq!(pot.fix_plural_forms_count(), 2);
+    assert_eq!(pot.messages[1].strings, ["a", "b", ""]);
+    assert_eq!(pot.messages[2].strings, ["a", "b", "c"]);
+    assert_eq!(pot.messages[3].strings, ["a", "b", "", "d"]);
+    assert_eq!(pot.plural_consistency(), [3]);
+    assert_eq!(pot.fix_plural_forms_count(), 0);
+}
+
+#[test]
+fn untranslated_template_plurals_are_consistent() {
+    let pot = read("msgid \"\"\nmsgstr \"Plural-Forms: nplurals=3; plural=(n==1 ? 0 : n==2 ? 1 : 2);\\n\"\n\n\
+        msgid \"file\"\nmsgid_plural \"files\"\n\n\
+        msgid \"short\"\nmsgid_plural \"shorts\"\nmsgstr[0] \"a\"\n");
+    assert!(pot.messages[1].strings.is_empty());
+    assert_eq!(pot.plural_consistency(), [2]);
+}
+
+#[test]
+fn missing_forms_leave_an_entry_incomplete() {
+    let pot = read("msgid \"\"\nmsgstr \"Plural-Forms: nplurals=3; plural=(n==1 ? 0 : n==2 ? 1 : 2);\\n\"\n\n\
+        msgid \"file\"\nmsgid_plural \"files\"\nmsgstr[0] \"a\"\nmsgstr[1] \"b\"\n\n\
+        msgid \"dir\"\nmsgid_plural \"dirs\"\nmsgstr[0] \"a\"\nmsgstr[1] \"b\"\nmsgstr[2] \"c\"\n");
+    let stats = pot.stats();
+    assert_eq!((stats.total, stats.translated, stats.untranslated), (2, 1, 1));
+    assert_eq!(pot.completeness(), 0.5);
+}
+
+#[test]
+fn length_outliers_measure_plural_forms_against_msgid_plural() {
+    let pot = read("msgid \"a\"\nmsgid_plural \"a long plural\"\nmsgstr[0] \"b\"\nmsgstr[1] \"a long plural\"\n\n\
+        msgid \"c\"\nmsgid_plural \"cs\"\nmsgstr[0] \"a long singular\"\nmsgstr[1] \"cs\"\n");
+    assert_eq!(pot.length_outliers(2.0), [1]);
+}
@@ -0,0 +1,276 @@
+use potty::{ParseWarning, ParseWarningKind, Parser, ParserOptions, Pot};
+
+fn read(s: &str) -> Pot {
+    Pot::read(&mut s.as_bytes())
+}
+
+fn read_with_warnings(s: &str) -> (Pot, Vec<ParseWarning>) {
+    Pot::read_with_warnings(&mut s.as_bytes(), &ParserOptions::default())
+}
+
+#[test]
+fn out_of_order_plural_forms_are_stored_by_index() {
+    let mut pot = read("msgid \"file\"\nmsgid_plural \"files\"\nmsgstr[1] \"Dateien\"\nmsgstr[0] \"Datei\"\n");
+    let message = &mut pot.messages[0];
+    assert_eq!(message.strings, ["Datei", "Dateien"]);
+    message.sort_plural_forms();
+    assert_eq!(message.strings, ["Datei", "Dateien"]);
+    assert!(message.to_string().ends_with("msgstr[0] \"Datei\"\nmsgstr[1] \"Dateien\"\n"));
+}
+
+#[test]
+fn huge_plural_index_is_skipped() {
+    for index in ["50000000", "18446744073709551615", "99999999999999999999999"] {
+        let source = format!("msgid \"file\"\nmsgid_plural \"files\"\nmsgstr[0] \"Datei\"\nmsgstr[{}] \"x\"\n\"y\"\n", index);
+        let (pot, warnings) = read_with_warnings(&source);
+        assert_eq!(pot.messages[0].strings, ["Datei"]);
+        assert!(matches!(warnings[..], [ParseWarning { line: 4, kind: ParseWarningKind::PluralIndexOutOfRange(_) }]));
+    }
+}
+
+#[test]
+fn untranslated_plural_template_round_trips() {
+    let pot = read("msgid \"\"\nmsgstr \"Plural-Forms: nplurals=3; plural=(n==1 ? 0 : n==2 ? 1 : 2);\\n\"\n\n\
+        msgid \"file\"\nmsgid_plural \"files\"\n");
+    let message = pot.find(None, "file").unwrap();
+    assert_eq!(message.id_plural.as_deref(), Some("files"));
+    assert!(message.strings.is_empty());
+    assert!(!message.is_translated());
+
+    let mut written = Vec::new();
+    pot.write(&mut written).unwrap();
+    let written = String::from_utf8(written).unwrap();
+    assert!(written.ends_with("msgid_plural \"files\"\nmsgstr[0] \"\"\nmsgstr[1] \"\"\nmsgstr[2] \"\"\n"));
+    assert!(pot.is_roundtrip_stable());
+}
+
+#[test]
+fn final_entry_without_trailing_newline() {
+    let pot = read("msgid \"a\"\nmsgstr \"A\"\n\nmsgid \"b\"\nmsgstr \"B\"");
+    assert_eq!(pot.messages.len(), 2);
+    assert_eq!(pot.gettext("b"), "B");
+
+    let pot = read("msgid \"a\"\nmsgstr \"\"\n\"A\"");
+    assert_eq!(pot.gettext("a"), "A");
+}
+
+#[test]
+fn try_read_rejects_unknown_keywords() {
+    let source = "msgid \"a\"\nmsgsrt \"A\"\n";
+    let error = Pot::try_read_with_options(&mut source.as_bytes(), &ParserOptions::default()).err().unwrap();
+    assert_eq!(error, ParseWarning { line: 2, kind: ParseWarningKind::UnknownKeyword("msgsrt".to_string()) });
+
+    let (pot, warnings) = read_with_warnings(source);
+    assert!(pot.messages[0].strings.is_empty());
+    assert_eq!(warnings.len(), 1);
+}
+
+#[test]
+fn long_lines_and_long_wrapped_strings() {
+    let unit = "abc \\\"def\\\" \\\\ ";
+    let long = unit.repeat(250_000);
+    let source = format!("msgid \"long\"\nmsgstr \"{}\"\n\nmsgid \"wrapped\"\nmsgstr \"\"\n{}", long, format!("\"{}\"\n", unit).repeat(100_000));
+    let pot = read(&source);
+    let expected = "abc \"def\" \\ ";
+    assert_eq!(pot.gettext("long"), expected.repeat(250_000));
+    assert_eq!(pot.gettext("wrapped"), expected.repeat(100_000));
+
+    // An unterminated value isn't a keyword line, however long it is.
+    let pot = read(&format!("msgid \"a\"\nmsgstr \"{}\n", long));
+    assert!(pot.messages[0].strings.is_empty());
+}
+
+#[test]
+fn empty_header_followed_by_entries() {
+    let mut pot = read("msgid \"\"\nmsgstr \"\"\n\nmsgid \"a\"\nmsgstr \"A\"\n");
+    assert!(pot.header().is_some());
+    assert_eq!(pot.header_field("Language"), None);
+    assert_eq!(pot.nplurals(), None);
+    assert!(pot.plural_selector().is_err());
+    assert_eq!(pot.gettext("a"), "A");
+
+    pot.set_header_field("Language", "de");
+    assert_eq!(pot.header_field("Language"), Some("de"));
+    assert_eq!(pot.messages.len(), 2);
+    assert!(pot.messages[0].is_header());
+}
+
+#[test]
+fn empty_segment_in_wrapped_string() {
+    let pot = read("msgid \"\"\n\"Hello, \"\n\"\"\n\"world\"\nmsgstr \"Hallo, \"\n\"\"\n\"\"\n\"Welt\"\n");
+    assert_eq!(pot.gettext("Hello, world"), "Hallo, Welt");
+}
+
+#[test]
+fn keyword_like_values_are_not_keywords() {
+    let pot = read("msgid \"msgstr is a keyword\"\nmsgstr \"\"\n\"msgid foo\"\n\nmsgid \"\"\n\"msgid foo\"\nmsgstr \"bar\"\n");
+    assert_eq!(pot.messages.len(), 2);
+    assert_eq!(pot.gettext("msgstr is a keyword"), "msgid foo");
+    assert_eq!(pot.gettext("msgid foo"), "bar");
+}
+
+#[test]
+fn live_obsolete_live_transitions() {
+    let source = "msgid \"a\"\nmsgstr \"A\"\n\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n\nmsgid \"b\"\nmsgstr \"B\"\n";
+    let pot = read(source);
+    let states: Vec<(&str, bool)> = pot.messages.iter().map(|m| (m.id.as_deref().unwrap(), m.obsolete)).collect();
+    assert_eq!(states, [("a", false), ("old", true), ("b", false)]);
+    assert_eq!(pot.messages[2].strings, ["B"]);
+    assert!(pot.is_roundtrip_stable());
+
+    let mut written = Vec::new();
+    pot.write_with_options(&mut written, &potty::WriteOptions { faithful: true, ..Default::default() }).unwrap();
+    assert_eq!(String::from_utf8(written).unwrap(), source);
+}
+
+#[test]
+fn headerless_catalog_has_no_phantom_entries() {
+    let pot = read("msgid \"Foo\"\nmsgstr \"Bar\"\n\nmsgid \"Baz\"\nmsgstr \"Qux\"\n\n");
+    let messages: Vec<(Option<&str>, &[String])> = pot.messages.iter().map(|m| (m.id.as_deref(), &m.strings[..])).collect();
+    assert_eq!(messages, [(Some("Foo"), &["Bar".to_string()][..]), (Some("Baz"), &["Qux".to_string()][..])]);
+    assert!(pot.header().is_none());
+}
+
+#[test]
+fn msgstr_before_msgid_is_skipped() {
+    let (pot, warnings) = read_with_warnings("msgid \"a\"\nmsgstr \"A\"\n\nmsgstr \"stray\"\nmsgid \"b\"\nmsgstr \"B\"\n");
+    assert_eq!(warnings, [ParseWarning { line: 4, kind: ParseWarningKind::MisplacedKeyword("msgstr".to_string()) }]);
+    assert_eq!(pot.messages.len(), 2);
+    assert_eq!(pot.gettext("a"), "A");
+    assert_eq!(pot.gettext("b"), "B");
+    assert!(pot.messages.iter().all(|m| m.strings.len() == 1));
+}
+
+#[test]
+fn keyword_alias() {
+    let mut options = ParserOptions::default();
+    options.keyword_aliases.insert("msgtxt".to_string(), "msgstr".to_string());
+    let source = "msgid \"a\"\nmsgtxt \"A\"\n";
+    let pot = Pot::read_with_options(&mut source.as_bytes(), &options);
+    assert_eq!(pot.gettext("a"), "A");
+
+    let (pot, warnings) = read_with_warnings(source);
+    assert_eq!(pot.gettext("a"), "a");
+    assert_eq!(warnings, [ParseWarning { line: 2, kind: ParseWarningKind::UnknownKeyword("msgtxt".to_string()) }]);
+}
+
+#[test]
+fn custom_comment_prefix() {
+    let options = ParserOptions { comment_prefix: ";".to_string(), ..Default::default() };
+    let source = "; translator\n;: src/a.rs:1\n;, fuzzy\nmsgid \"a\"\nmsgstr \"A\"\n";
+    let pot = Pot::read_with_options(&mut source.as_bytes(), &options);
+    assert_eq!(pot.messages.len(), 1);
+    let message = &pot.messages[0];
+    assert_eq!(message.translator_note(), "translator");
+    assert_eq!(message.references(), [("src/a.rs", Some(1))]);
+    assert!(message.is_fuzzy());
+}
+
+#[test]
+fn multi_line_header_accumulates() {
+    let pot = read("msgid \"\"\nmsgstr \"\"\n\"Project-Id-Version: potty 1.0\\n\"\n\"Language: de\\n\"\n\
+        \"Content-Type: text/plain; \"\n\"charset=UTF-8\\n\"\n\"Plural-Forms: nplurals=2; plural=(n != 1);\\n\"\n");
+    assert_eq!(pot.messages.len(), 1);
+    assert_eq!(pot.header().unwrap().strings, ["Project-Id-Version: potty 1.0\nLanguage: de\nContent-Type: text/plain; charset=UTF-8\nPlural-Forms: nplurals=2; plural=(n != 1);\n"]);
+    assert_eq!(pot.header_field("Project-Id-Version"), Some("potty 1.0"));
+    assert_eq!(pot.header_field("Content-Type"), Some("text/plain; charset=UTF-8"));
+    assert_eq!(pot.nplurals(), Some(2));
+}
+
+#[test]
+fn fuzzy_flag_variations() {
+    for flags in ["#, Fuzzy", "#,fuzzy", "#, fuzzy , c-format", "#, c-format,FUZZY"] {
+        let pot = read(&format!("{}\nmsgid \"a\"\nmsgstr \"A\"\n", flags));
+        let message = &pot.messages[0];
+        assert!(message.is_fuzzy(), "{}", flags);
+        assert!(message.is_translated());
+        assert_eq!(pot.gettext("a"), "a", "{}", flags);
+        assert!(!message.flags().iter().any(|f| f.contains(' ')), "{}", flags);
+    }
+    let pot = read("#, c-format\nmsgid \"a\"\nmsgstr \"A\"\n");
+    assert!(!pot.messages[0].is_fuzzy());
+}
+
+#[test]
+fn wrapped_msgid_with_escaped_quotes() {
+    let pot = read("msgid \"\"\n\"He said \\\"hi\"\n\"\\\" and \\\"\"\n\"bye\\\\\\\"\"\nmsgstr \"x\"\n");
+    assert_eq!(pot.messages[0].id.as_deref(), Some("He said \"hi\" and \"bye\\\""));
+}
+
+#[test]
+fn obsolete_marker_followed_by_tab_or_nothing() {
+    let pot = read("msgid \"a\"\nmsgstr \"A\"\n\n#~\tmsgid \"tab\"\n#~\tmsgstr \"Tab\"\n\n#~msgid \"none\"\n#~msgstr \"\"\n#~\t\"None\"\n");
+    assert_eq!(pot.messages.len(), 3);
+    let tab = pot.find_including_obsolete(None, "tab").unwrap();
+    assert!(tab.obsolete);
+    assert_eq!(tab.strings, ["Tab"]);
+    let none = pot.find_including_obsolete(None, "none").unwrap();
+    assert!(none.obsolete);
+    assert_eq!(none.strings, ["None"]);
+}
+
+#[test]
+fn doubled_empty_msgstr_is_collapsed() {
+    let (pot, warnings) = read_with_warnings("msgid \"a\"\nmsgstr \"\"\nmsgstr \"\"\n\nmsgid \"b\"\nmsgstr \"\"\nmsgstr \"B\"\n");
+    assert_eq!(pot.messages.len(), 2);
+    assert_eq!(pot.messages[0].strings, [""]);
+    assert_eq!(pot.messages[1].strings, ["B"]);
+    assert!(pot.validate().is_empty());
+    assert_eq!(warnings.iter().map(|w| (w.line, &w.kind)).collect::<Vec<_>>(), [(3, &ParseWarningKind::DuplicateMsgstr), (7, &ParseWarningKind::DuplicateMsgstr)]);
+}
+
+#[test]
+fn unicode_and_byte_escapes() {
+    let cases = [
+        ("caf\\u00e9", "café"),
+        ("\\U0001F389", "🎉"),
+        ("\\uD83D\\uDE00", "😀"),
+        ("caf\\303\\251", "café"),
+        ("caf\\xc3\\xa9", "café"),
+        ("\\342\\202\\254 5", "€ 5"),
+        ("caf\\351", "café"),
+        ("\\101\\102", "AB"),
+        ("\\u00e", "\\u00e"),
+        ("\\q", "\\q"),
+    ];
+    for (escaped, expected) in cases {
+        let pot = read(&format!("msgid \"a\"\nmsgstr \"{}\"\n", escaped));
+        assert_eq!(pot.gettext("a"), expected, "{}", escaped);
+    }
+}
+
+#[test]
+fn indented_lines() {
+    let pot = read("   # note\n\t#: src/a.rs:1\n  msgid \"a\"\n  msgstr \"A\"\n");
+    assert_eq!(pot.messages.len(), 1);
+    let message = &pot.messages[0];
+    assert_eq!(message.translator_note(), "note");
+    assert_eq!(message.references(), [("src/a.rs", Some(1))]);
+    assert_eq!(pot.gettext("a"), "A");
+}
+
+#[test]
+fn parser_resyncs_mid_entry() {
+    let options = ParserOptions::default();
+    let mut parser = Parser::new(&options);
+    parser.resync();
+    let fragment = "\"rest of a wrapped msgid\"\nmsgstr \"partial\"\nmsgid_plural \"x\"\n\n#: src/b.rs:2\nmsgid \"b\"\nmsgstr \"B\"\n\nmsgid \"c\"\nmsgstr \"C\"\n";
+    for line in fragment.lines() {
+        assert_eq!(parser.feed_line(line), None);
+    }
+    let pot = parser.finish();
+    let ids: Vec<&str> = pot.messages.iter().map(|m| m.id.as_deref().unwrap()).collect();
+    assert_eq!(ids, ["b", "c"]);
+    assert_eq!(pot.messages[0].references(), [("src/b.rs", Some(2))]);
+}
+
+#[test]
+fn msgid_plural_after_msgstr_forms() {
+    let (pot, warnings) = read_with_warnings("msgid \"file\"\nmsgstr[0] \"Datei\"\nmsgstr[1] \"Dateien\"\nmsgid_plural \"files\"\n\nmsgid \"b\"\nmsgstr \"B\"\n");
+    assert_eq!(warnings, [ParseWarning { line: 4, kind: ParseWarningKind::MsgidPluralAfterMsgstr }]);
+    assert_eq!(pot.messages.len(), 2);
+    let message = &pot.messages[0];
+    assert_eq!(message.id_plural.as_deref(), Some("files"));
+    assert_eq!(message.strings, ["Datei", "Dateien"]);
+    assert_eq!(pot.messages[1].strings, ["B"]);
+}
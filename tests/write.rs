@@ -0,0 +1,304 @@
+use potty::{LineEnding, ParserOptions, Pot, PotComment, PotCommentKind, WriteOptions};
+
+fn read(s: &str) -> Pot {
+    Pot::read(&mut s.as_bytes())
+}
+
+fn write(pot: &Pot) -> String {
+    write_with_options(pot, &WriteOptions::default())
+}
+
+fn write_with_options(pot: &Pot, options: &WriteOptions) -> String {
+    let mut buf = Vec::new();
+    pot.write_with_options(&mut buf, options).unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+#[test]
+fn interleaved_obsolete_entries_are_roundtrip_stable() {
+    let pot = read("msgid \"a\"\nmsgstr \"A\"\n\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n\nmsgid \"b\"\nmsgstr \"B\"\n");
+    assert!(pot.is_roundtrip_stable());
+    assert!(write(&pot).ends_with("#~ msgid \"old\"\n#~ msgstr \"Alt\"\n"));
+}
+
+#[test]
+fn collapsed_obsolete_lines_are_roundtrip_stable() {
+    let source = "msgid \"a\"\nmsgstr \"A\"\n\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n";
+    let pot = Pot::read_with_options(&mut source.as_bytes(), &ParserOptions { collapse_obsolete: true, ..Default::default() });
+    assert_eq!(pot.obsolete_lines.len(), 2);
+    assert!(pot.is_roundtrip_stable());
+}
+
+#[test]
+fn collapsed_obsolete_lines_are_kept_verbatim() {
+    let source = "msgid \"a\"\nmsgstr \"A\"\n\n#,fuzzy\n  #~ msgid \"old\"\r\n#~msgstr \"Alt\"\n";
+    let pot = Pot::read_with_options(&mut source.as_bytes(), &ParserOptions { collapse_obsolete: true, ..Default::default() });
+    assert_eq!(pot.obsolete_lines, ["#,fuzzy", "  #~ msgid \"old\"", "#~msgstr \"Alt\""]);
+    assert_eq!(write(&pot), source.replace('\r', ""));
+}
+
+#[test]
+fn trailing_comments_stay_after_the_last_entry() {
+    let source = "#: src/a.rs:1\nmsgid \"a\"\nmsgstr \"A\"\n# end of file\n";
+    let pot = read(source);
+    assert_eq!(pot.messages.len(), 1);
+    assert_eq!(write(&pot), source);
+    assert_eq!(write_with_options(&pot, &WriteOptions { faithful: true, ..Default::default() }), source);
+}
+
+#[test]
+fn trailing_comments_belong_to_the_catalog() {
+    let source = "# note for a\nmsgid \"a\"\nmsgid_plural \"as\"\nmsgstr[0] \"\"\n\n# end of file\n";
+    let mut pot = read(source);
+    assert_eq!(pot.trailing_comments, ["", "# end of file"]);
+    assert_eq!(pot.messages[0].translator_note(), "note for a");
+    assert_eq!(write(&pot), source);
+
+    pot.set_header_field("Plural-Forms", "nplurals=2; plural=(n != 1);");
+    pot.fix_plural_forms_count();
+    assert!(write(&pot).ends_with("msgstr[1] \"\"\n\n# end of file\n"));
+
+    pot.messages.push(read("msgid \"b\"\nmsgstr \"\"\n").messages.remove(0));
+    assert!(write(&pot).ends_with("msgid \"b\"\nmsgstr \"\"\n\n# end of file\n"));
+    assert!(pot.is_roundtrip_stable());
+}
+
+fn read_keeping_original(s: &str) -> Pot {
+    Pot::read_with_options(&mut s.as_bytes(), &ParserOptions { keep_original: true, ..Default::default() })
+}
+
+#[test]
+fn unmodified_entries_are_written_verbatim() {
+    let source = "msgid \"\"\nmsgstr \"\"\n\"Language: de\\n\"\n\n\
+        #: src/a.rs:1\nmsgid \"a\"\nmsgstr \"\"\n\"A\"\n\n\
+        msgid \"b\"\nmsgstr \"\\102\"\n\n\
+        #,fuzzy\nmsgid \"c\"\nmsgstr \"C\"\n\n\
+        # end of file\n";
+    let mut pot = read_keeping_original(source);
+    assert_eq!(write(&pot), source);
+
+    pot.messages.iter_mut().find(|m| m.id.as_deref() == Some("b")).unwrap().strings[0] = "Be".to_string();
+    let expected = source.replace("msgstr \"\\102\"", "msgstr \"Be\"");
+    assert_eq!(write(&pot), expected);
+}
+
+#[test]
+fn comment_order_is_kept() {
+    let source = "#, c-format\n#: src/a.rs:1\n#. extracted\n# translator\nmsgid \"%d a\"\nmsgstr \"%d A\"\n";
+    let pot = read(source);
+    assert_eq!(write(&pot), source);
+}
+
+#[test]
+fn backslash_in_msgid_is_keyed_unescaped_and_escaped_on_write() {
+    let source = "msgctxt \"dir\"\nmsgid \"path\\\\to\"\nmsgstr \"Pfad\\\\zu\"\n";
+    let pot = read(source);
+    let message = pot.find(Some("dir"), "path\\to").unwrap();
+    assert_eq!(message.id.as_deref(), Some("path\\to"));
+    assert_eq!(message.key(), "dir\u{4}path\\to");
+    assert_eq!(pot.pairs().next(), Some(("dir\u{4}path\\to".to_string(), "Pfad\\zu")));
+    assert_eq!(write(&pot), source);
+}
+
+/// Accepts at most three bytes per call, as a pipe or socket may.
+struct ShortWriter(Vec<u8>);
+
+impl std::io::Write for ShortWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(3);
+        self.0.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn short_writes_are_completed() {
+    let pot = read("msgid \"\"\nmsgstr \"Language: de\\n\"\n\n#: src/a.rs:1\nmsgid \"a\"\nmsgstr \"A\"\n\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n");
+    let mut writer = ShortWriter(Vec::new());
+    pot.write(&mut writer).unwrap();
+    assert_eq!(String::from_utf8(writer.0).unwrap(), write(&pot));
+}
+
+#[test]
+fn references_across_several_lines() {
+    let source = "#: src/a.rs:1\n#: src/b.rs:2 src/c.rs:3\n#: src/d.rs\nmsgid \"a\"\nmsgstr \"A\"\n";
+    let pot = read(source);
+    let message = &pot.messages[0];
+    assert_eq!(message.references(), [("src/a.rs", Some(1)), ("src/b.rs", Some(2)), ("src/c.rs", Some(3)), ("src/d.rs", None)]);
+    assert_eq!(write(&pot), source);
+    assert_eq!(
+        write_with_options(&pot, &WriteOptions { fold_references: true, ..Default::default() }),
+        "#: src/a.rs:1 src/b.rs:2 src/c.rs:3 src/d.rs\nmsgid \"a\"\nmsgstr \"A\"\n",
+    );
+}
+
+#[test]
+fn crlf_input_is_written_with_lf() {
+    let source = "# note\r\nmsgid \"\"\r\nmsgstr \"\"\r\n\"Language: de\\n\"\r\n\r\nmsgid \"a\"\r\nmsgstr \"\"\r\n\"A\"\r\n";
+    let pot = read(source);
+    assert_eq!(pot.gettext("a"), "A");
+    let written = write(&pot);
+    assert!(!written.contains('\r'));
+    assert_eq!(written, "# note\nmsgid \"\"\nmsgstr \"Language: de\\n\"\n\nmsgid \"a\"\nmsgstr \"A\"\n");
+}
+
+#[test]
+fn comment_prefixes() {
+    let cases = [
+        (PotCommentKind::Reference, "src/a.rs:1", "#: src/a.rs:1"),
+        (PotCommentKind::Extracted, "note", "#. note"),
+        (PotCommentKind::Flag, "c-format", "#, c-format"),
+        (PotCommentKind::Previous, "msgid \"old\"", "#| msgid \"old\""),
+        (PotCommentKind::Translator, "translator", "# translator"),
+        (PotCommentKind::Translator, "", "#"),
+    ];
+    for (kind, content, expected) in cases {
+        let comment = PotComment { kind, content: content.to_string(), fields_before: 0 };
+        assert_eq!(comment.to_string(), expected);
+        assert!(expected.parse::<PotComment>().unwrap() == comment);
+    }
+}
+
+#[test]
+fn escapes() {
+    let mut pot = Pot::new();
+    let mut message = potty::PotMessage::new();
+    message.id = Some("a".to_string());
+    message.strings.push("tab\there \"quoted\" bell\u{7} back\\slash\u{1}\nnext".to_string());
+    pot.messages.push(message);
+    let written = write(&pot);
+    assert_eq!(written, "msgid \"a\"\nmsgstr \"\"\n\"tab\\there \\\"quoted\\\" bell\\a back\\\\slash\\001\\n\"\n\"next\"\n");
+    assert!(read(&written) == pot);
+}
+
+#[test]
+fn characters_outside_the_bmp() {
+    let source = "# Übersetzung für 🎉\n#. 注意\nmsgid \"Party\"\nmsgstr \"Fête 🎉🥳\"\n";
+    let pot = read(source);
+    let message = &pot.messages[0];
+    assert_eq!(message.strings, ["Fête 🎉🥳"]);
+    assert_eq!(message.translator_note(), "Übersetzung für 🎉");
+    assert_eq!(message.comments[1].content, "注意");
+    assert_eq!(write(&pot), source);
+}
+
+#[test]
+fn faithful_layout() {
+    let source = "#: src/a.rs:1\nmsgctxt \"menu\"\n# about the msgid\nmsgid \"a\"\n#, fuzzy\nmsgstr \"A\"\n";
+    let pot = read(source);
+    let faithful = WriteOptions { faithful: true, ..Default::default() };
+    assert_eq!(write_with_options(&pot, &faithful), source);
+    assert_eq!(write(&pot), "#: src/a.rs:1\n# about the msgid\n#, fuzzy\nmsgctxt \"menu\"\nmsgid \"a\"\nmsgstr \"A\"\n");
+}
+
+#[test]
+fn comments_between_plural_keywords() {
+    let sources = [
+        "msgid \"file\"\nmsgid_plural \"files\"\n# note\nmsgstr[0] \"Datei\"\nmsgstr[1] \"Dateien\"\n",
+        "msgid \"file\"\nmsgid_plural \"files\"\nmsgstr[0] \"Datei\"\n# note\nmsgstr[1] \"Dateien\"\n",
+    ];
+    let faithful = WriteOptions { faithful: true, ..Default::default() };
+    for source in sources {
+        let (pot, warnings) = Pot::read_with_warnings(&mut source.as_bytes(), &ParserOptions::default());
+        assert!(warnings.is_empty(), "{}", source);
+        assert_eq!(pot.messages.len(), 1);
+        assert_eq!(pot.messages[0].strings, ["Datei", "Dateien"]);
+        assert_eq!(write_with_options(&pot, &faithful), source);
+        assert_eq!(write(&pot), "# note\nmsgid \"file\"\nmsgid_plural \"files\"\nmsgstr[0] \"Datei\"\nmsgstr[1] \"Dateien\"\n");
+        assert_eq!(write_with_options(&read_keeping_original(source), &faithful), source);
+    }
+}
+
+#[test]
+fn obsolete_entries_are_grouped_at_the_end() {
+    let source = "msgid \"a\"\nmsgstr \"A\"\n\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n\nmsgid \"b\"\nmsgstr \"B\"\n";
+    let pot = read(source);
+    assert_eq!(write(&pot), "msgid \"a\"\nmsgstr \"A\"\n\nmsgid \"b\"\nmsgstr \"B\"\n\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n");
+    assert_eq!(write_with_options(&pot, &WriteOptions { faithful: true, ..Default::default() }), source);
+}
+
+#[test]
+fn obsolete_entry_with_flags() {
+    let expected = "#, fuzzy, c-format\n#~| msgid \"older\"\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n";
+    for source in [expected, "#~ #, fuzzy, c-format\n#~| msgid \"older\"\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n"] {
+        let pot = read(source);
+        assert_eq!(pot.messages.len(), 1);
+        let message = &pot.messages[0];
+        assert!(message.obsolete);
+        assert!(message.is_fuzzy());
+        assert_eq!(message.flags(), ["fuzzy", "c-format"]);
+        assert_eq!(write(&pot), expected);
+    }
+}
+
+#[test]
+fn comment_kinds_can_be_left_out() {
+    let source = "# translator\n#. extracted\n#: src/a.rs:1\n#, c-format\nmsgid \"%d a\"\nmsgstr \"%d A\"\n";
+    let pot = read(source);
+    let options = WriteOptions { comment_kinds: vec![PotCommentKind::Translator, PotCommentKind::Flag], ..Default::default() };
+    assert_eq!(write_with_options(&pot, &options), "# translator\n#, c-format\nmsgid \"%d a\"\nmsgstr \"%d A\"\n");
+    assert_eq!(pot.messages[0].comments.len(), 4);
+    assert_eq!(pot.messages[0].references(), [("src/a.rs", Some(1))]);
+    assert_eq!(write(&pot), source);
+}
+
+#[test]
+fn header_is_written_one_field_per_line() {
+    let mut pot = Pot::new();
+    pot.set_header_field("Language", "de");
+    pot.set_header_field("Content-Type", "text/plain; charset=UTF-8");
+    pot.set_header_field("Plural-Forms", "nplurals=2; plural=(n != 1);");
+    assert_eq!(write(&pot), "msgid \"\"\nmsgstr \"\"\n\"Language: de\\n\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n\"Plural-Forms: nplurals=2; plural=(n != 1);\\n\"\n");
+}
+
+#[test]
+fn crlf_line_endings() {
+    let pot = read("msgid \"\"\nmsgstr \"Language: de\\n\"\n\n#: src/a.rs:1\nmsgid \"a\"\nmsgstr \"line\\r\\nnext\"\n\n#~ msgid \"old\"\n#~ msgstr \"Alt\"\n");
+    let written = write_with_options(&pot, &WriteOptions { line_ending: LineEnding::Crlf, ..Default::default() });
+    assert_eq!(
+        written,
+        "msgid \"\"\r\nmsgstr \"Language: de\\n\"\r\n\r\n#: src/a.rs:1\r\nmsgid \"a\"\r\nmsgstr \"\"\r\n\"line\\r\\n\"\r\n\"next\"\r\n\r\n#~ msgid \"old\"\r\n#~ msgstr \"Alt\"\r\n",
+    );
+    assert_eq!(written.matches('\r').count(), written.matches("\r\n").count());
+    assert!(read(&written) == pot);
+}
+
+#[test]
+fn fuzzy_is_written_first() {
+    let pot = read("#, c-format, fuzzy\nmsgid \"%d a\"\nmsgstr \"%d A\"\n");
+    assert_eq!(write(&pot), "#, fuzzy, c-format\nmsgid \"%d a\"\nmsgstr \"%d A\"\n");
+
+    let spaced = read("#, fuzzy,c-format\nmsgid \"%d a\"\nmsgstr \"%d A\"\n");
+    assert_eq!(write(&spaced), "#, fuzzy, c-format\nmsgid \"%d a\"\nmsgstr \"%d A\"\n");
+    assert!(spaced.messages[0].comments[0] == pot.messages[0].comments[0]);
+
+    let mut pot = read("#, c-format\nmsgid \"%d a\"\nmsgstr \"%d A\"\n");
+    pot.messages[0].mark_fuzzy();
+    assert_eq!(write(&pot), "#, fuzzy, c-format\nmsgid \"%d a\"\nmsgstr \"%d A\"\n");
+}
+
+#[test]
+fn rust_source_escapes_keys_and_values() {
+    let mut pot = read("msgid \"Say \\\"hi\\\"\"\nmsgstr \"Sag \\\"hallo\\\"\"\n\n\
+        msgctxt \"path\"\nmsgid \"C:\\\\dir\"\nmsgstr \"C:\\\\Ordner\"\n\n\
+        msgid \"bell\"\nmsgstr \"ding\"\n");
+    pot.messages[2].strings[0].push('\u{7}');
+    let mut buf = Vec::new();
+    pot.write_rust(&mut buf).unwrap();
+    let expected = r#"// Generated from a PO catalog. Do not edit.
+
+pub fn lookup(key: &str) -> Option<&'static str> {
+    match key {
+        "Say \"hi\"" => Some("Sag \"hallo\""),
+        "bell" => Some("ding\u{7}"),
+        "path\u{4}C:\\dir" => Some("C:\\Ordner"),
+        _ => None,
+    }
+}
+"#;
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+}